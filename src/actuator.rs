@@ -0,0 +1,140 @@
+use crate::hardware::HardwareConfig;
+use crate::sensor::{CurrentSensor, LightLevelError};
+use rppal::gpio::{Gpio, InputPin, OutputPin};
+use thiserror::Error;
+
+/// Direction the motor drives the door in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Open,
+    Close,
+}
+
+/// The hardware the door state machine drives: a reversible motor, a limit switch at the open position
+/// and optionally another at the closed position
+pub trait DoorActuator {
+    fn set_direction(&mut self, direction: Direction);
+    fn set_motor_enabled(&mut self, enabled: bool);
+    /// Current reading of the open limit switch
+    fn open_switch_hit(&mut self) -> Result<bool, ActuatorError>;
+
+    fn has_closed_switch(&self) -> bool {
+        false
+    }
+
+    /// Current reading of the closed limit switch, always `false` if there is none
+    fn closed_switch_hit(&mut self) -> Result<bool, ActuatorError> {
+        Ok(false)
+    }
+
+    /// Motor current in amps while it is above the obstruction threshold, `None` if it is normal or not sensed
+    fn motor_overloaded(&mut self) -> Result<Option<f64>, ActuatorError> {
+        Ok(None)
+    }
+}
+
+impl<T: DoorActuator + ?Sized> DoorActuator for Box<T> {
+    fn set_direction(&mut self, direction: Direction) {
+        (**self).set_direction(direction)
+    }
+
+    fn set_motor_enabled(&mut self, enabled: bool) {
+        (**self).set_motor_enabled(enabled)
+    }
+
+    fn open_switch_hit(&mut self) -> Result<bool, ActuatorError> {
+        (**self).open_switch_hit()
+    }
+
+    fn has_closed_switch(&self) -> bool {
+        (**self).has_closed_switch()
+    }
+
+    fn closed_switch_hit(&mut self) -> Result<bool, ActuatorError> {
+        (**self).closed_switch_hit()
+    }
+
+    fn motor_overloaded(&mut self) -> Result<Option<f64>, ActuatorError> {
+        (**self).motor_overloaded()
+    }
+}
+
+/// Motor controller and limit switch wired to the Raspberry Pi's GPIO header
+pub struct RppalActuator {
+    limit_pin: InputPin,
+    closed_limit_pin: Option<InputPin>,
+    mff_pin: OutputPin,
+    me_pin: OutputPin,
+    current_sensor: Option<CurrentSensor>,
+}
+
+impl RppalActuator {
+    pub fn new(config: &HardwareConfig) -> Result<Self, ActuatorError> {
+        let gpio = Gpio::new()?;
+        let limit_pin = gpio.get(config.limit_pin)?.into_input_pullup();
+        let closed_limit_pin = match config.closed_limit_pin {
+            Some(pin) => Some(gpio.get(pin)?.into_input_pullup()),
+            None => None,
+        };
+        let mut mff_pin = gpio.get(config.motor_flip_flop_pin)?.into_output();
+        let mut me_pin = gpio.get(config.motor_enable_pin)?.into_output();
+        mff_pin.set_reset_on_drop(false);
+        me_pin.set_reset_on_drop(false);
+        let current_sensor = match &config.current_sensor {
+            Some(current_sensor) => Some(current_sensor.build()?),
+            None => None,
+        };
+        Ok(Self {
+            limit_pin,
+            closed_limit_pin,
+            mff_pin,
+            me_pin,
+            current_sensor,
+        })
+    }
+}
+
+impl DoorActuator for RppalActuator {
+    fn set_direction(&mut self, direction: Direction) {
+        match direction {
+            Direction::Open => self.mff_pin.set_low(),
+            Direction::Close => self.mff_pin.set_high(),
+        }
+    }
+
+    fn set_motor_enabled(&mut self, enabled: bool) {
+        if enabled {
+            self.me_pin.set_high();
+        } else {
+            self.me_pin.set_low();
+        }
+    }
+
+    fn open_switch_hit(&mut self) -> Result<bool, ActuatorError> {
+        Ok(self.limit_pin.is_low())
+    }
+
+    fn has_closed_switch(&self) -> bool {
+        self.closed_limit_pin.is_some()
+    }
+
+    fn closed_switch_hit(&mut self) -> Result<bool, ActuatorError> {
+        Ok(self.closed_limit_pin.as_ref().is_some_and(|pin| pin.is_low()))
+    }
+
+    fn motor_overloaded(&mut self) -> Result<Option<f64>, ActuatorError> {
+        let Some(sensor) = self.current_sensor.as_mut() else {
+            return Ok(None);
+        };
+        let current = sensor.current()?;
+        Ok((current > sensor.threshold()).then_some(current))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ActuatorError {
+    #[error("could not access gpio")]
+    Gpio(#[from] rppal::gpio::Error),
+    #[error("could not read motor current")]
+    CurrentSensor(#[from] LightLevelError),
+}
@@ -8,7 +8,18 @@ use leptos_router::{
 };
 use thaw::ssr::SSRMountStyleProvider;
 use thaw::*;
-use crate::settings::{Settings, Times, LightLevels};
+use crate::settings::{
+    DateOverride, DayPlan, LightFilter, LightLevels, LightUnit, ScheduleMode, Settings, SettingsError, SettingsField,
+    SolarTimes, Times, TriggerPolicy, Triggers, Twilight, WeekdayTimes, SETTINGS_VERSION,
+};
+use crate::status::{
+    Command, CommandId, CommandProgress, DoorStatus, HoldDuration, ManualHold, ObstructionEvent, OneShotCommand,
+    OneShotError, ScheduledEvent, State,
+};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc, Weekday};
+use std::time::Duration;
+
+const POLL_STATUS_MSECS: u64 = 1000;
 
 pub fn shell(options: LeptosOptions) -> impl IntoView {
     view! {
@@ -74,6 +85,49 @@ fn PageNotFound() -> impl IntoView {
 fn ControlPanel() -> impl IntoView {
     let close_clicked = ServerAction::<Close>::new();
     let open_clicked = ServerAction::<Open>::new();
+    let stop_clicked = ServerAction::<Stop>::new();
+    let reset_clicked = ServerAction::<Reset>::new();
+    let cancel_hold_clicked = ServerAction::<CancelHold>::new();
+    let lockdown_clicked = ServerAction::<SetLockdown>::new();
+    let hold_kind = RwSignal::new("next".to_string());
+    let hold_hours = RwSignal::new(2u32);
+    let hold = move || match hold_kind.get().as_str() {
+        "hours" => HoldDuration::Hours(hold_hours.get()),
+        "forever" => HoldDuration::Indefinitely,
+        _ => HoldDuration::UntilNextTransition,
+    };
+    let last_command = RwSignal::new(None::<CommandId>);
+    Effect::new(move |_| {
+        if let Some(Ok(id)) = open_clicked.value().get() {
+            last_command.set(Some(id));
+        }
+    });
+    Effect::new(move |_| {
+        if let Some(Ok(id)) = close_clicked.value().get() {
+            last_command.set(Some(id));
+        }
+    });
+    Effect::new(move |_| {
+        if let Some(Ok(id)) = reset_clicked.value().get() {
+            last_command.set(Some(id));
+        }
+    });
+    let poll = use_poll();
+    let door_status = Resource::new(move || poll.get(), |_| get_door_state());
+    let obstructions = Resource::new(move || poll.get(), |_| get_obstructions());
+    let manual_hold = Resource::new(move || poll.get(), |_| get_manual_hold());
+    let next_events = Resource::new(move || poll.get(), |_| get_next_events());
+    let lockdown = Resource::new(move || (poll.get(), lockdown_clicked.version().get()), |_| get_lockdown());
+    let locked_down = Signal::derive(move || lockdown.get().and_then(Result::ok).unwrap_or(false));
+    let progress = Resource::new(
+        move || (last_command.get(), poll.get()),
+        |(id, _)| async move {
+            match id {
+                Some(id) => get_command_progress(id).await.ok(),
+                None => None,
+            }
+        },
+    );
 
     view! {
         <Layout>
@@ -83,31 +137,226 @@ fn ControlPanel() -> impl IntoView {
                     <CardHeader>
                         <b>"Control Panel"</b>
                     </CardHeader>
+                    <Transition>
+                        {move || Suspend::new(async move {
+                            door_status
+                                .await
+                                .ok()
+                                .map(|status| {
+                                    let held = matches!(status.state, State::Stopped | State::Fault);
+                                    view! {
+                                        <StatusBadge status />
+                                        <Show when=move || held>
+                                            <div>"Scheduled opening and closing is paused until the door is cleared"</div>
+                                            <Button on_click=move |_| {
+                                                reset_clicked.dispatch(Reset {});
+                                            }>"Clear and Resume"</Button>
+                                        </Show>
+                                    }
+                                })
+                        })}
+                    </Transition>
+                    <Button
+                        class="stop-button"
+                        icon=icondata::BsStopCircleFill
+                        on_click=move |_| {
+                            stop_clicked.dispatch(Stop {});
+                        }
+                    >
+                        "Stop"
+                    </Button>
+                    <Button
+                        disabled=locked_down
+                        on_click=move |_| {
+                            open_clicked.dispatch(Open { hold: hold() });
+                        }
+                    >
+                        "Open Door"
+                    </Button>
                     <Button on_click=move |_| {
-                        open_clicked.dispatch(Open {});
-                    }>"Open Door"</Button>
-                    <Button on_click=move |_| {
-                        close_clicked.dispatch(Close {});
+                        close_clicked.dispatch(Close { hold: hold() });
                     }>"Close Door"</Button>
+                    <Flex class="row">
+                        "Hold against the schedule"
+                        <Select value=hold_kind>
+                            <option value="next">"Until the next scheduled change"</option>
+                            <option value="hours">"For a number of hours"</option>
+                            <option value="forever">"Until cancelled"</option>
+                        </Select>
+                        <Show when=move || hold_kind.get() == "hours">
+                            <SpinButton<u32> value=hold_hours step_page=1 min=1 max=72 />
+                        </Show>
+                    </Flex>
+                    <Transition>
+                        {move || Suspend::new(async move {
+                            next_events.await.ok().map(|events| view! { <NextEvents events /> })
+                        })}
+                    </Transition>
+                    <Transition>
+                        {move || Suspend::new(async move {
+                            let lockdown = lockdown.await.unwrap_or(false);
+                            let (label, color) = if lockdown {
+                                ("End Lockdown", ButtonAppearance::Primary)
+                            } else {
+                                ("Lockdown", ButtonAppearance::Secondary)
+                            };
+                            view! {
+                                <Button
+                                    appearance=color
+                                    icon=icondata::BsShieldLockFill
+                                    on_click=move |_| {
+                                        lockdown_clicked.dispatch(SetLockdown { lockdown: !lockdown });
+                                    }
+                                >
+                                    {label}
+                                </Button>
+                            }
+                        })}
+                    </Transition>
+                    <Transition>
+                        {move || Suspend::new(async move {
+                            manual_hold
+                                .await
+                                .ok()
+                                .flatten()
+                                .map(|hold| {
+                                    view! {
+                                        <Flex class="row">
+                                            <HoldNotice hold />
+                                            <Button on_click=move |_| {
+                                                cancel_hold_clicked.dispatch(CancelHold {});
+                                            }>"Resume Schedule"</Button>
+                                        </Flex>
+                                    }
+                                })
+                        })}
+                    </Transition>
+                    <Transition>
+                        {move || Suspend::new(async move {
+                            progress
+                                .await
+                                .map(|progress| {
+                                    let text = match progress {
+                                        CommandProgress::Queued => "Queued".to_string(),
+                                        CommandProgress::Running => "Moving".to_string(),
+                                        CommandProgress::Completed => "Done".to_string(),
+                                        CommandProgress::Failed(e) => format!("Failed: {e}"),
+                                        CommandProgress::Unknown => "Unknown".to_string(),
+                                    };
+                                    view! { <div>"Last command: " {text}</div> }
+                                })
+                        })}
+                    </Transition>
+                    <Transition>
+                        {move || Suspend::new(async move {
+                            obstructions
+                                .await
+                                .ok()
+                                .and_then(|obstructions| obstructions.last().cloned())
+                                .map(|obstruction| view! { <LastObstruction obstruction /> })
+                        })}
+                    </Transition>
                 </Card>
+                <OneShotPanel />
             </Flex>
         </Layout>
     }
 }
 
+#[component]
+fn OneShotPanel() -> impl IntoView {
+    let add_clicked = ServerAction::<AddOneShot>::new();
+    let cancel_clicked = ServerAction::<CancelOneShot>::new();
+    let poll = use_poll();
+    let one_shots = Resource::new(
+        move || (poll.get(), add_clicked.version().get(), cancel_clicked.version().get()),
+        |_| get_one_shots(),
+    );
+    let date = RwSignal::new(Utc::now().date_naive());
+    let time = RwSignal::new(NaiveTime::from_hms_opt(9, 0, 0).unwrap());
+    let command = RwSignal::new("open".to_string());
+    let note = RwSignal::new(String::new());
+
+    view! {
+        <Card>
+            <CardHeader>
+                <b>"Scheduled Commands"</b>
+            </CardHeader>
+            <Transition>
+                {move || Suspend::new(async move {
+                    one_shots
+                        .await
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|one_shot| {
+                            let action = match one_shot.command {
+                                Command::Close => "Close",
+                                _ => "Open",
+                            };
+                            view! {
+                                <Flex class="row">
+                                    {format!("{action} at {} {}", one_shot.at.format("%Y-%m-%d %H:%M"), one_shot.note)}
+                                    <Button on_click=move |_| {
+                                        cancel_clicked.dispatch(CancelOneShot { id: one_shot.id });
+                                    }>"Cancel"</Button>
+                                </Flex>
+                            }
+                        })
+                        .collect_view()
+                })}
+            </Transition>
+            <Flex class="row">
+                <Select value=command>
+                    <option value="open">"Open"</option>
+                    <option value="close">"Close"</option>
+                </Select>
+                <DatePicker value=date />
+                <TimePicker value=time />
+            </Flex>
+            <Flex class="row">
+                <Input value=note placeholder="Note" />
+                <Button on_click=move |_| {
+                    let command = match command.get().as_str() {
+                        "close" => Command::Close,
+                        _ => Command::Open,
+                    };
+                    add_clicked.dispatch(AddOneShot {
+                        at: date.get().and_time(time.get()),
+                        command,
+                        note: note.get(),
+                    });
+                    note.set(String::new());
+                }>"Add"</Button>
+            </Flex>
+            {move || {
+                add_clicked
+                    .value()
+                    .get()
+                    .and_then(|result| result.ok()?.err())
+                    .map(|error| view! { <div class="field-error">{error.to_string()}</div> })
+            }}
+        </Card>
+    }
+}
+
 #[component]
 fn SettingsPanel() -> impl IntoView {
     let (pending, set_pending) = signal(false);
     let write_settings = ServerAction::<WriteSettings>::new();
-    let settings = Resource::new(
-        move || {
-            (
-                write_settings.version().get(),
-                write_settings.version().get(),
-            )
+    // Only reload the form once settings are saved, so rejected changes stay on screen to be fixed
+    let saved = RwSignal::new(0u32);
+    let errors = RwSignal::new(Vec::<SettingsError>::new());
+    Effect::new(move |_| match write_settings.value().get() {
+        Some(Ok(Ok(()))) => {
+            errors.set(Vec::new());
+            saved.update(|saved| *saved += 1);
         },
-        move |_| get_settings(),
-    );
+        Some(Ok(Err(rejected))) => errors.set(rejected),
+        _ => {},
+    });
+    let settings = Resource::new(move || saved.get(), move |_| get_settings());
+    let light_unit = Resource::new(|| (), |_| get_light_unit());
+    let todays_plan = Resource::new(move || saved.get(), |_| get_todays_plan());
 
     view! {
         <Layout>
@@ -125,22 +374,111 @@ fn SettingsPanel() -> impl IntoView {
                     >
                         {move || Suspend::new(async move {
                             let settings = settings.await.unwrap();
+                            let light_unit = light_unit.await.unwrap_or_default();
                             let open_time = RwSignal::new(settings.times.open);
                             let close_time = RwSignal::new(settings.times.close);
+                            let timezone = RwSignal::new(settings.timezone.clone().unwrap_or_default());
                             let close_light_level = RwSignal::new(settings.light_levels.close);
                             let open_light_level = RwSignal::new(settings.light_levels.open);
+                            let follow_sun = RwSignal::new(settings.schedule == ScheduleMode::Solar);
+                            let latitude = RwSignal::new(settings.solar.latitude);
+                            let longitude = RwSignal::new(settings.solar.longitude);
+                            let twilight = RwSignal::new(settings.solar.twilight.name().to_string());
+                            let open_offset = RwSignal::new(settings.solar.open_offset_mins);
+                            let close_offset = RwSignal::new(settings.solar.close_offset_mins);
+                            let weekly = WEEKDAYS.map(|weekday| {
+                                let times = settings.weekly.iter().find(|times| times.weekday == weekday);
+                                let default = times.map_or(settings.times.clone(), |times| times.times.clone());
+                                (
+                                    weekday,
+                                    RwSignal::new(times.is_some()),
+                                    RwSignal::new(default.open),
+                                    RwSignal::new(default.close),
+                                )
+                            });
+                            let overrides = RwSignal::new(settings.overrides.clone());
+                            let median_samples = RwSignal::new(settings.light_filter.median_samples);
+                            let hysteresis = RwSignal::new(settings.light_filter.hysteresis);
+                            let dwell_mins = RwSignal::new(settings.light_filter.dwell_mins);
+                            let open_trigger = TriggerSignals::new(&settings.triggers.open, open_time.get_untracked());
+                            let close_trigger = TriggerSignals::new(&settings.triggers.close, close_time.get_untracked());
                             {
                                 view! {
                                     <Flex class="row">
-                                        <div class="label">"Open time"</div>
-                                        <TimePicker value=open_time />
+                                        "Timezone"
+                                        <Input value=timezone placeholder="Device timezone, e.g. Europe/London" />
                                     </Flex>
+                                    <FieldErrors errors field=SettingsField::Timezone />
                                     <Flex class="row">
-                                        "Close time" <TimePicker value=close_time />
+                                        "Follow sunrise and sunset" <Switch checked=follow_sun />
                                     </Flex>
+                                    <Show
+                                        when=move || follow_sun.get()
+                                        fallback=move || view! {
+                                            <Flex class="row">
+                                                <div class="label">"Open time"</div>
+                                                <TimePicker value=open_time />
+                                            </Flex>
+                                            <Flex class="row">
+                                                "Close time" <TimePicker value=close_time />
+                                            </Flex>
+                                            <FieldErrors errors field=SettingsField::Times />
+                                        }
+                                    >
+                                        <Flex class="row">
+                                            "Latitude (°N)"
+                                            <SpinButton<f64> value=latitude step_page=1.0 min=-90.0 max=90.0 />
+                                        </Flex>
+                                        <Flex class="row">
+                                            "Longitude (°E)"
+                                            <SpinButton<f64> value=longitude step_page=1.0 min=-180.0 max=180.0 />
+                                        </Flex>
+                                        <Flex class="row">
+                                            "Sunrise and sunset"
+                                            <Select value=twilight>
+                                                <option value="official">"Official"</option>
+                                                <option value="civil">"Civil twilight"</option>
+                                                <option value="nautical">"Nautical twilight"</option>
+                                            </Select>
+                                        </Flex>
+                                        <Flex class="row">
+                                            "Open (minutes after sunrise)"
+                                            <SpinButton<i64> value=open_offset step_page=15 min=-720 max=720 />
+                                        </Flex>
+                                        <Flex class="row">
+                                            "Close (minutes after sunset)"
+                                            <SpinButton<i64> value=close_offset step_page=15 min=-720 max=720 />
+                                        </Flex>
+                                        <FieldErrors errors field=SettingsField::Solar />
+                                    </Show>
+                                    <Transition>
+                                        {move || Suspend::new(async move {
+                                            todays_plan
+                                                .await
+                                                .ok()
+                                                .map(|plan| {
+                                                    let text = match plan {
+                                                        DayPlan::Times(times) => format!(
+                                                            "Today the door opens at {} and closes at {}",
+                                                            times.open.format("%H:%M"),
+                                                            times.close.format("%H:%M"),
+                                                        ),
+                                                        DayPlan::KeepClosed => "Today the door is kept closed".to_string(),
+                                                        DayPlan::KeepOpen => "Today the door is kept open".to_string(),
+                                                    };
+                                                    view! { <div>{text}</div> }
+                                                })
+                                        })}
+                                    </Transition>
+                                    <TriggerPolicyEditor label="Open when" trigger=open_trigger />
+                                    <FieldErrors errors field=SettingsField::OpenTrigger />
+                                    <TriggerPolicyEditor label="Close when" trigger=close_trigger />
+                                    <FieldErrors errors field=SettingsField::CloseTrigger />
+                                    <WeeklyTimesEditor weekly errors />
+                                    <DateOverridesEditor overrides errors />
                                     <Flex class="row">
-                                        "Open light level" <Flex>
-                                            <Slider step=5.0 show_stops=false value=open_light_level>
+                                        {format!("Open light level ({})", light_unit.symbol())} <Flex>
+                                            <Slider step=5.0 max=light_unit.max() show_stops=false value=open_light_level>
                                                 <SliderLabel value=open_light_level>
                                                     {open_light_level}
                                                 </SliderLabel>
@@ -158,8 +496,8 @@ fn SettingsPanel() -> impl IntoView {
                                         </Flex>
                                     </Flex>
                                     <Flex class="row">
-                                        "Close light level" <Flex>
-                                            <Slider step=5.0 show_stops=false value=close_light_level>
+                                        {format!("Close light level ({})", light_unit.symbol())} <Flex>
+                                            <Slider step=5.0 max=light_unit.max() show_stops=false value=close_light_level>
                                                 <SliderLabel value=close_light_level>
                                                     {close_light_level}
                                                 </SliderLabel>
@@ -175,6 +513,20 @@ fn SettingsPanel() -> impl IntoView {
                                             >"Use Current Reading"</Button>
                                         </Flex>
                                     </Flex>
+                                    <FieldErrors errors field=SettingsField::LightLevels />
+                                    <Flex class="row">
+                                        "Smooth light over (readings)"
+                                        <SpinButton<usize> value=median_samples step_page=5 min=1 max=60 />
+                                    </Flex>
+                                    <Flex class="row">
+                                        {format!("Light hysteresis ({})", light_unit.symbol())}
+                                        <SpinButton<f64> value=hysteresis step_page=5.0 min=0.0 max=light_unit.max() />
+                                    </Flex>
+                                    <Flex class="row">
+                                        "Light must hold for (minutes)"
+                                        <SpinButton<u64> value=dwell_mins step_page=5 min=0 max=120 />
+                                    </Flex>
+                                    <FieldErrors errors field=SettingsField::LightFilter />
                                     <CardFooter>
                                         <Button
                                             icon=icondata::BsCheckLg
@@ -183,6 +535,7 @@ fn SettingsPanel() -> impl IntoView {
                                                     write_settings
                                                         .dispatch(
                                                             Settings {
+                                                                version: SETTINGS_VERSION,
                                                                 light_levels: LightLevels {
                                                                     close: close_light_level.get(),
                                                                     open: open_light_level.get(),
@@ -191,6 +544,43 @@ fn SettingsPanel() -> impl IntoView {
                                                                     open: open_time.get(),
                                                                     close: close_time.get(),
                                                                 },
+                                                                timezone: Some(timezone.get().trim().to_string())
+                                                                    .filter(|name| !name.is_empty()),
+                                                                schedule: if follow_sun.get() {
+                                                                    ScheduleMode::Solar
+                                                                } else {
+                                                                    ScheduleMode::Fixed
+                                                                },
+                                                                solar: SolarTimes {
+                                                                    latitude: latitude.get(),
+                                                                    longitude: longitude.get(),
+                                                                    twilight: Twilight::from_name(&twilight.get())
+                                                                        .unwrap_or_default(),
+                                                                    open_offset_mins: open_offset.get(),
+                                                                    close_offset_mins: close_offset.get(),
+                                                                },
+                                                                weekly: weekly
+                                                                    .iter()
+                                                                    .filter(|(_, enabled, _, _)| enabled.get())
+                                                                    .map(|(weekday, _, open, close)| WeekdayTimes {
+                                                                        weekday: *weekday,
+                                                                        times: Times {
+                                                                            open: open.get(),
+                                                                            close: close.get(),
+                                                                        },
+                                                                    })
+                                                                    .collect(),
+                                                                overrides: overrides.get(),
+                                                                triggers: Triggers {
+                                                                    open: open_trigger.policy(),
+                                                                    close: close_trigger.policy(),
+                                                                },
+                                                                light_filter: LightFilter {
+                                                                    median_samples: median_samples.get(),
+                                                                    hysteresis: hysteresis.get(),
+                                                                    dwell_mins: dwell_mins.get(),
+                                                                },
+                                                                lockdown: settings.lockdown,
                                                             }
                                                                 .into(),
                                                         );
@@ -199,6 +589,9 @@ fn SettingsPanel() -> impl IntoView {
                                         >
                                             "Apply"
                                         </Button>
+                                        <Show when=move || !errors.get().is_empty()>
+                                            <div class="field-error">"Not saved, fix the problems above first"</div>
+                                        </Show>
                                     </CardFooter>
                                 }
                             }
@@ -210,9 +603,304 @@ fn SettingsPanel() -> impl IntoView {
     }
 }
 
+/// Form state of a [`TriggerPolicy`]
+#[derive(Clone, Copy)]
+struct TriggerSignals {
+    name: RwSignal<String>,
+    earliest: RwSignal<NaiveTime>,
+    latest: RwSignal<NaiveTime>,
+}
+
+impl TriggerSignals {
+    /// `time` seeds the window bounds when `policy` has none
+    fn new(policy: &TriggerPolicy, time: NaiveTime) -> Self {
+        let (earliest, latest) = match policy {
+            TriggerPolicy::LightInWindow { earliest, latest } => (*earliest, *latest),
+            _ => (time, time),
+        };
+        Self {
+            name: RwSignal::new(policy.name().to_string()),
+            earliest: RwSignal::new(earliest),
+            latest: RwSignal::new(latest),
+        }
+    }
+
+    fn policy(&self) -> TriggerPolicy {
+        match self.name.get().as_str() {
+            "time_only" => TriggerPolicy::TimeOnly,
+            "light_only" => TriggerPolicy::LightOnly,
+            "time_and_light" => TriggerPolicy::TimeAndLight,
+            "light_in_window" => TriggerPolicy::LightInWindow {
+                earliest: self.earliest.get(),
+                latest: self.latest.get(),
+            },
+            _ => TriggerPolicy::TimeOrLight,
+        }
+    }
+}
+
+#[component]
+fn TriggerPolicyEditor(label: &'static str, trigger: TriggerSignals) -> impl IntoView {
+    view! {
+        <Flex class="row">
+            <div class="label">{label}</div>
+            <Select value=trigger.name>
+                <option value="time_only">"Time is reached"</option>
+                <option value="light_only">"Light level is reached"</option>
+                <option value="time_or_light">"Time or light level is reached"</option>
+                <option value="time_and_light">"Time and light level are reached"</option>
+                <option value="light_in_window">"Light level is reached between"</option>
+            </Select>
+            <Show when=move || trigger.name.get() == "light_in_window">
+                <TimePicker value=trigger.earliest />
+                "and"
+                <TimePicker value=trigger.latest />
+            </Show>
+        </Flex>
+    }
+}
+
+/// A day of the week, whether it has its own times, and those times
+type WeekdayRow = (Weekday, RwSignal<bool>, RwSignal<NaiveTime>, RwSignal<NaiveTime>);
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// Problems the server found with `field` when the settings were last applied
+#[component]
+fn FieldErrors(errors: RwSignal<Vec<SettingsError>>, field: SettingsField) -> impl IntoView {
+    move || {
+        errors
+            .get()
+            .into_iter()
+            .filter(|error| error.field() == field)
+            .map(|error| view! { <div class="field-error">{error.to_string()}</div> })
+            .collect_view()
+    }
+}
+
+#[component]
+fn WeeklyTimesEditor(weekly: [WeekdayRow; 7], errors: RwSignal<Vec<SettingsError>>) -> impl IntoView {
+    weekly
+        .into_iter()
+        .map(|(weekday, enabled, open, close)| {
+            view! {
+                <Flex class="row">
+                    <div class="label">{weekday.to_string()}</div>
+                    <Switch checked=enabled label="Own times" />
+                    <Show when=move || enabled.get()>
+                        <TimePicker value=open />
+                        <TimePicker value=close />
+                    </Show>
+                </Flex>
+                <FieldErrors errors field=SettingsField::Weekday(weekday) />
+            }
+        })
+        .collect_view()
+}
+
+#[component]
+fn DateOverridesEditor(overrides: RwSignal<Vec<DateOverride>>, errors: RwSignal<Vec<SettingsError>>) -> impl IntoView {
+    let today = Utc::now().date_naive();
+    let start = RwSignal::new(today);
+    let end = RwSignal::new(today);
+    let plan = RwSignal::new("keep_closed".to_string());
+    let open = RwSignal::new(Times::default().open);
+    let close = RwSignal::new(Times::default().close);
+    let note = RwSignal::new(String::new());
+    let add = move |_| {
+        let plan = match plan.get().as_str() {
+            "keep_open" => DayPlan::KeepOpen,
+            "times" => DayPlan::Times(Times {
+                open: open.get(),
+                close: close.get(),
+            }),
+            _ => DayPlan::KeepClosed,
+        };
+        if end.get() >= start.get() {
+            overrides.update(|overrides| {
+                overrides.push(DateOverride {
+                    start: start.get(),
+                    end: end.get(),
+                    plan,
+                    note: note.get(),
+                })
+            });
+            note.set(String::new());
+        }
+    };
+
+    view! {
+        <b>"Date overrides"</b>
+        {move || {
+            overrides
+                .get()
+                .into_iter()
+                .enumerate()
+                .map(|(index, date_override)| {
+                    let plan = match date_override.plan {
+                        DayPlan::Times(times) => {
+                            format!("open {} close {}", times.open.format("%H:%M"), times.close.format("%H:%M"))
+                        }
+                        DayPlan::KeepClosed => "kept closed".to_string(),
+                        DayPlan::KeepOpen => "kept open".to_string(),
+                    };
+                    view! {
+                        <Flex class="row">
+                            {format!(
+                                "{} to {}: {plan} {}",
+                                format_date(date_override.start),
+                                format_date(date_override.end),
+                                date_override.note,
+                            )}
+                            <Button on_click=move |_| {
+                                overrides.update(|overrides| {
+                                    overrides.remove(index);
+                                });
+                            }>"Remove"</Button>
+                        </Flex>
+                        <FieldErrors errors field=SettingsField::Override(index) />
+                    }
+                })
+                .collect_view()
+        }}
+        <Flex class="row">
+            <DatePicker value=start />
+            <DatePicker value=end />
+            <Select value=plan>
+                <option value="keep_closed">"Keep closed"</option>
+                <option value="keep_open">"Keep open"</option>
+                <option value="times">"Own times"</option>
+            </Select>
+        </Flex>
+        <Show when=move || plan.get() == "times">
+            <Flex class="row">
+                <TimePicker value=open />
+                <TimePicker value=close />
+            </Flex>
+        </Show>
+        <Flex class="row">
+            <Input value=note placeholder="Note" />
+            <Button on_click=add>"Add Override"</Button>
+        </Flex>
+    }
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+#[component]
+fn StatusBadge(status: DoorStatus) -> impl IntoView {
+    let color = match status.state {
+        State::Open => BadgeColor::Success,
+        State::Closed => BadgeColor::Informative,
+        State::Opening | State::Closing => BadgeColor::Warning,
+        State::Fault | State::Stopped => BadgeColor::Danger,
+        State::Unknown => BadgeColor::Subtle,
+    };
+    let elapsed = elapsed_since(status.since);
+
+    view! {
+        <Flex>
+            <Badge color size=BadgeSize::Large>{status.state.to_string()}</Badge>
+            {format!("for {elapsed}")}
+        </Flex>
+        {status.fault.map(|fault| view! { <div>{fault}</div> })}
+    }
+}
+
+#[component]
+fn LastObstruction(obstruction: ObstructionEvent) -> impl IntoView {
+    let during = match obstruction.during {
+        State::Opening => "opening",
+        State::Closing => "closing",
+        _ => "moving",
+    };
+    view! {
+        <div>
+            {format!(
+                "Obstructed {} ago while {during}, motor drew {:.2} A",
+                elapsed_since(obstruction.at),
+                obstruction.current,
+            )}
+        </div>
+    }
+}
+
+#[component]
+fn NextEvents(events: Vec<ScheduledEvent>) -> impl IntoView {
+    let next = |command: Command| {
+        events
+            .iter()
+            .find(|event| event.command == command)
+            .map_or("not scheduled".to_string(), |event| event.at.format("%a %H:%M").to_string())
+    };
+    view! {
+        <div>{format!("Next open: {} / Next close: {}", next(Command::Open), next(Command::Close))}</div>
+    }
+}
+
+#[component]
+fn HoldNotice(hold: ManualHold) -> impl IntoView {
+    let door = match hold.command {
+        Command::Close => "closed",
+        _ => "open",
+    };
+    let until = match hold.duration {
+        HoldDuration::UntilNextTransition => "until the next scheduled change".to_string(),
+        HoldDuration::Hours(_) => match hold.expires() {
+            Some(expires) => format!("for another {}", format_duration(expires - Utc::now())),
+            None => String::new(),
+        },
+        HoldDuration::Indefinitely => "until the schedule is resumed".to_string(),
+    };
+    view! { <div>{format!("Door held {door} by hand {until}")}</div> }
+}
+
+/// Time since `since`, to the nearest second
+fn elapsed_since(since: DateTime<Utc>) -> String {
+    format_duration(Utc::now() - since)
+}
+
+fn format_duration(duration: TimeDelta) -> String {
+    let duration = duration.max(TimeDelta::zero());
+    if duration.num_hours() > 0 {
+        format!("{}h {}m", duration.num_hours(), duration.num_minutes() % 60)
+    } else if duration.num_minutes() > 0 {
+        format!("{}m {}s", duration.num_minutes(), duration.num_seconds() % 60)
+    } else {
+        format!("{}s", duration.num_seconds())
+    }
+}
+
+/// Counter that ticks every [`POLL_STATUS_MSECS`] in the browser, for refetching door status
+fn use_poll() -> ReadSignal<u64> {
+    let (poll, set_poll) = signal(0u64);
+    Effect::new(move |_| {
+        if let Ok(handle) = set_interval_with_handle(
+            move || set_poll.update(|poll| *poll += 1),
+            Duration::from_millis(POLL_STATUS_MSECS),
+        ) {
+            on_cleanup(move || handle.clear());
+        }
+    });
+    poll
+}
+
 #[component]
 fn NavBar() -> impl IntoView {
     let navigate = RwSignal::new(use_navigate());
+    let poll = use_poll();
+    let lockdown = Resource::new(move || poll.get(), |_| get_lockdown());
+    let clock_problem = Resource::new(move || poll.get(), |_| get_clock_problem());
     let theme = Theme::use_rw_theme();
     let theme_name = Memo::new(move |_| {
         theme.with(|theme| {
@@ -250,6 +938,35 @@ fn NavBar() -> impl IntoView {
                 >
                     <b>"Settings"</b>
                 </Button>
+                <Transition>
+                    {move || Suspend::new(async move {
+                        lockdown
+                            .await
+                            .unwrap_or(false)
+                            .then(|| {
+                                view! {
+                                    <Badge color=BadgeColor::Danger size=BadgeSize::Large>
+                                        "Lockdown: door kept closed"
+                                    </Badge>
+                                }
+                            })
+                    })}
+                </Transition>
+                <Transition>
+                    {move || Suspend::new(async move {
+                        clock_problem
+                            .await
+                            .ok()
+                            .flatten()
+                            .map(|problem| {
+                                view! {
+                                    <Badge color=BadgeColor::Warning size=BadgeSize::Large>
+                                        {format!("The {problem}: following the light only")}
+                                    </Badge>
+                                }
+                            })
+                    })}
+                </Transition>
             </Flex>
             <Button
                 icon=Memo::new(move |_| {
@@ -273,20 +990,119 @@ fn NavBar() -> impl IntoView {
     name = Close,
     endpoint = "close_door",
 )]
-async fn close() -> Result<(), ServerFnError> {
-    crate::door::close();
-    Ok(())
+async fn close(hold: HoldDuration) -> Result<CommandId, ServerFnError> {
+    Ok(crate::door::manual_command(Command::Close, hold)?)
 }
 
 #[server(
     name = Open,
     endpoint = "open_door",
 )]
-async fn open() -> Result<(), ServerFnError> {
-    crate::door::open();
+async fn open(hold: HoldDuration) -> Result<CommandId, ServerFnError> {
+    Ok(crate::door::manual_command(Command::Open, hold)?)
+}
+
+#[server(
+    name = GetNextEvents,
+    endpoint = "next_events",
+)]
+async fn get_next_events() -> Result<Vec<ScheduledEvent>, ServerFnError> {
+    let settings = crate::door::get_settings()?;
+    let now = crate::timezone::ScheduleZone::of(&settings).local(crate::door::now());
+    Ok(crate::scheduler::preview(&settings, now, now + TimeDelta::days(7)))
+}
+
+#[server(
+    name = GetOneShots,
+    endpoint = "one_shots",
+)]
+async fn get_one_shots() -> Result<Vec<OneShotCommand>, ServerFnError> {
+    Ok(crate::door::one_shots()?)
+}
+
+#[server(
+    name = AddOneShot,
+    endpoint = "add_one_shot",
+)]
+async fn add_one_shot(
+    at: NaiveDateTime,
+    command: Command,
+    note: String,
+) -> Result<Result<u64, OneShotError>, ServerFnError> {
+    use crate::door::OneShotIOError;
+    let zone = crate::timezone::ScheduleZone::of(&crate::door::get_settings()?);
+    match crate::door::add_one_shot(crate::door::now(), zone, at, command, note) {
+        Ok(id) => Ok(Ok(id)),
+        Err(OneShotIOError::Rejected(e)) => Ok(Err(e)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[server(
+    name = CancelOneShot,
+    endpoint = "cancel_one_shot",
+)]
+async fn cancel_one_shot(id: u64) -> Result<(), ServerFnError> {
+    Ok(crate::door::cancel_one_shot(id)?)
+}
+
+#[server(
+    name = GetManualHold,
+    endpoint = "manual_hold",
+)]
+async fn get_manual_hold() -> Result<Option<ManualHold>, ServerFnError> {
+    Ok(crate::door::manual_hold(crate::door::now()))
+}
+
+#[server(
+    name = CancelHold,
+    endpoint = "cancel_hold",
+)]
+async fn cancel_hold() -> Result<(), ServerFnError> {
+    crate::door::cancel_hold();
     Ok(())
 }
 
+#[server(
+    name = Stop,
+    endpoint = "stop_door",
+)]
+async fn stop() -> Result<(), ServerFnError> {
+    Ok(crate::door::stop()?)
+}
+
+#[server(
+    name = Reset,
+    endpoint = "reset_door",
+)]
+async fn reset() -> Result<CommandId, ServerFnError> {
+    Ok(crate::door::reset()?)
+}
+
+#[server(
+    name = GetDoorState,
+    endpoint = "door_state",
+)]
+async fn get_door_state() -> Result<DoorStatus, ServerFnError> {
+    Ok(crate::door::status()?)
+}
+
+#[server(
+    name = GetCommandProgress,
+    endpoint = "command_progress",
+)]
+async fn get_command_progress(id: CommandId) -> Result<CommandProgress, ServerFnError> {
+    Ok(crate::door::command_progress(id))
+}
+
+#[server(
+    name = GetObstructions,
+    endpoint = "obstructions",
+)]
+async fn get_obstructions() -> Result<Vec<ObstructionEvent>, ServerFnError> {
+    Ok(crate::door::obstructions())
+}
+
 #[server(
     name = GetSettings,
     endpoint = "get_settings",
@@ -299,8 +1115,41 @@ async fn get_settings() -> Result<Settings, ServerFnError> {
     name = WriteSettings,
     endpoint = "write_settings",
 )]
-async fn write_settings(settings: Settings) -> Result<(), ServerFnError> {
-    Ok(crate::door::write_settings(settings)?)
+async fn write_settings(settings: Settings) -> Result<Result<(), Vec<SettingsError>>, ServerFnError> {
+    // Lockdown is toggled from the control panel, so the settings form may hold a stale value
+    let settings = Settings {
+        lockdown: crate::door::get_settings()?.lockdown,
+        ..settings
+    };
+    if let Err(errors) = settings.validate() {
+        return Ok(Err(errors));
+    }
+    crate::door::write_settings(settings)?;
+    Ok(Ok(()))
+}
+
+#[server(
+    name = GetClockProblem,
+    endpoint = "clock_problem",
+)]
+async fn get_clock_problem() -> Result<Option<String>, ServerFnError> {
+    Ok(crate::time_sync::check(crate::door::clock().as_ref()).err().map(|e| e.to_string()))
+}
+
+#[server(
+    name = GetLockdown,
+    endpoint = "lockdown",
+)]
+async fn get_lockdown() -> Result<bool, ServerFnError> {
+    Ok(crate::door::get_settings()?.lockdown)
+}
+
+#[server(
+    name = SetLockdown,
+    endpoint = "set_lockdown",
+)]
+async fn set_lockdown(lockdown: bool) -> Result<(), ServerFnError> {
+    Ok(crate::door::set_lockdown(lockdown)?)
 }
 
 
@@ -312,3 +1161,21 @@ async fn light_level() -> Result<f64, ServerFnError> {
     println!("Getting light level");
     Ok(crate::door::light_level()?)
 }
+
+#[server(
+    name = GetTodaysPlan,
+    endpoint = "todays_plan",
+)]
+async fn get_todays_plan() -> Result<DayPlan, ServerFnError> {
+    let settings = crate::door::get_settings()?;
+    let today = crate::timezone::ScheduleZone::of(&settings).today(crate::door::now());
+    Ok(crate::scheduler::day_plan(&settings, today))
+}
+
+#[server(
+    name = GetLightUnit,
+    endpoint = "light_unit",
+)]
+async fn get_light_unit() -> Result<LightUnit, ServerFnError> {
+    Ok(crate::door::light_unit())
+}
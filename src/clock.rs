@@ -0,0 +1,126 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Future returned by [`Clock::sleep`]
+pub type Sleep<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// Source of time for the scheduler and the door's motion sequences, so they can run against simulated time
+pub trait Clock: Send + Sync {
+    /// Wall clock time, which can jump when the system clock is set
+    fn now(&self) -> DateTime<Utc>;
+    /// Monotonic time for measuring how long the motor has run
+    fn instant(&self) -> Instant;
+    fn sleep(&self, duration: Duration) -> Sleep<'_>;
+    /// Whether the wall clock is being kept in step with a time source such as NTP
+    fn synchronized(&self) -> bool;
+}
+
+/// The system clock and tokio timers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep<'_> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+
+    /// Asks the kernel, which an NTP client such as chrony keeps informed
+    #[cfg(target_os = "linux")]
+    fn synchronized(&self) -> bool {
+        // SAFETY: with `modes` zeroed adjtimex only reads the kernel's clock state into `timex`
+        let mut timex: libc::timex = unsafe { std::mem::zeroed() };
+        let state = unsafe { libc::adjtimex(&mut timex) };
+        state != -1 && state != libc::TIME_ERROR && timex.status & libc::STA_UNSYNC == 0
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn synchronized(&self) -> bool {
+        true
+    }
+}
+
+/// Simulated time that only moves when told to. Sleeping advances it by the time slept, so a door sequence
+/// or a day of scheduling runs as fast as the code allows.
+#[derive(Debug)]
+pub struct FakeClock {
+    time: Mutex<FakeTime>,
+    synchronized: AtomicBool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FakeTime {
+    wall: DateTime<Utc>,
+    monotonic: Instant,
+}
+
+impl FakeClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            time: Mutex::new(FakeTime {
+                wall: start,
+                monotonic: Instant::now(),
+            }),
+            synchronized: AtomicBool::new(true),
+        }
+    }
+
+    /// Lets `duration` pass
+    pub fn advance(&self, duration: Duration) {
+        let mut time = self.lock();
+        time.wall += TimeDelta::from_std(duration).unwrap_or(TimeDelta::MAX);
+        time.monotonic += duration;
+    }
+
+    /// Lets time pass until `at`, doing nothing if it is already later than that
+    pub fn advance_to(&self, at: DateTime<Utc>) {
+        let remaining = at - self.now();
+        if let Ok(remaining) = remaining.to_std() {
+            self.advance(remaining);
+        }
+    }
+
+    /// Sets the wall clock to `at` without any time passing, as when the system clock is corrected
+    pub fn set(&self, at: DateTime<Utc>) {
+        self.lock().wall = at;
+    }
+
+    pub fn set_synchronized(&self, synchronized: bool) {
+        self.synchronized.store(synchronized, Ordering::Relaxed);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, FakeTime> {
+        // The time is plain data, so a panic elsewhere cannot leave it inconsistent
+        self.time.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.lock().wall
+    }
+
+    fn instant(&self) -> Instant {
+        self.lock().monotonic
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep<'_> {
+        self.advance(duration);
+        Box::pin(tokio::task::yield_now())
+    }
+
+    fn synchronized(&self) -> bool {
+        self.synchronized.load(Ordering::Relaxed)
+    }
+}
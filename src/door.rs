@@ -1,93 +1,806 @@
 use thiserror::Error;
-use crate::settings::Settings;
-use std::sync::{LazyLock, Mutex, MutexGuard};
-
-static DOOR_STATE: LazyLock<Mutex<State>> = LazyLock::new(|| Mutex::new(State::Closed));
-
-const LIMIT_PIN: u8 = 24;
-const MOTOR_FLIP_FLOP_PIN: u8 = 5;
-const MOTOR_ENABLE_PIN: u8 = 6;
-const DOOR_CLOSE_SECS: u64 = 5;
-const MFF_SAFETY_MSECS: u64 = 250;
-const OPEN_TIMEOUT_SECS: u64 = 6;
-
-pub fn close() {
-    use rppal::gpio::{Gpio, Trigger};
-    use std::time::Duration;
-    use std::thread;
-    match DOOR_STATE.lock() {
-        Ok(mut guard) => match *guard {
-            State::Open => {
-                let gpio = Gpio::new().expect("failed to open gpio interface");
-                let mut mff_pin = gpio.get(MOTOR_FLIP_FLOP_PIN).expect("failed to get motor flip flop pin").into_output();
-                let mut me_pin = gpio.get(MOTOR_ENABLE_PIN).expect("failed to get motor enable pin").into_output();
-                mff_pin.set_reset_on_drop(false);
-                me_pin.set_reset_on_drop(false);
-    
-                me_pin.set_low();
-                println!("Sleeping for {MFF_SAFETY_MSECS} milliseconds");
-                thread::sleep(Duration::from_millis(MFF_SAFETY_MSECS));
-                mff_pin.set_high();
-                me_pin.set_high();
-                println!("Sleeping for {DOOR_CLOSE_SECS} seconds");
-                thread::sleep(Duration::from_secs(DOOR_CLOSE_SECS));
-                mff_pin.set_low();
-                me_pin.set_low();
-                *guard = State::Closed;
+use crate::actuator::{ActuatorError, DoorActuator, Direction};
+use crate::clock::{Clock, SystemClock};
+use crate::hardware::MotionTimings;
+use crate::migration::{self, MigrationError};
+use crate::notification;
+use crate::sensor::{LightLevelError, LightSensor};
+use crate::settings::{LightUnit, Settings, SETTINGS_VERSION};
+use crate::simulation::{SimulatedActuator, SimulatedLightSensor, SimulationConfig};
+use crate::status::{
+    Command, CommandId, CommandProgress, DoorStatus, HoldDuration, ManualHold, ObstructionEvent, OneShotCommand,
+    OneShotError, State,
+};
+use crate::timezone::ScheduleZone;
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::watch;
+
+static DOOR: OnceLock<Arc<DoorHandle>> = OnceLock::new();
+static NEXT_COMMAND_ID: AtomicU64 = AtomicU64::new(0);
+static COMMAND_PROGRESS: Mutex<BTreeMap<CommandId, CommandProgress>> = Mutex::new(BTreeMap::new());
+static OBSTRUCTIONS: Mutex<VecDeque<ObstructionEvent>> = Mutex::new(VecDeque::new());
+/// Serializes reading and rewriting the one-shot file
+static ONE_SHOTS: Mutex<()> = Mutex::new(());
+static LIGHT_SENSOR: Mutex<Option<Box<dyn LightSensor + Send>>> = Mutex::new(None);
+
+const LIMIT_POLL_MSECS: u64 = 10;
+/// Consecutive polls a limit switch must read hit for, so a bounce or electrical noise is not taken for the door
+/// reaching it
+const LIMIT_DEBOUNCE_POLLS: u32 = 3;
+const COMMAND_HISTORY: usize = 32;
+const OBSTRUCTION_HISTORY: usize = 32;
+const STATE_FILE: &str = "./door_state.toml";
+const ONE_SHOT_FILE: &str = "./one_shots.toml";
+/// One-shot commands overdue by more than this, e.g. after a power cut, are dropped rather than run late
+const ONE_SHOT_GRACE_MINS: i64 = 15;
+
+/// Door state machine, driven through any [`DoorActuator`] backend
+pub struct Door<A: DoorActuator> {
+    actuator: A,
+    timings: MotionTimings,
+    clock: Arc<dyn Clock>,
+    status: watch::Sender<DoorStatus>,
+    /// Latched by [`Door::stop_handle`] holders, cleared by [`Door::reset`]
+    stop: Arc<watch::Sender<bool>>,
+}
+
+impl<A: DoorActuator> Door<A> {
+    pub fn new(actuator: A, timings: MotionTimings, clock: Arc<dyn Clock>, state: State) -> Self {
+        let status = DoorStatus::new(state, clock.now());
+        Self {
+            actuator,
+            timings,
+            clock,
+            status: watch::Sender::new(status),
+            stop: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.status.borrow().state
+    }
+
+    /// Receives every state change of the door
+    pub fn subscribe(&self) -> watch::Receiver<DoorStatus> {
+        self.status.subscribe()
+    }
+
+    /// Sending `true` aborts any motion in progress and latches the door in [`State::Stopped`]
+    pub fn stop_handle(&self) -> Arc<watch::Sender<bool>> {
+        self.stop.clone()
+    }
+
+    fn stop_requested(&self) -> bool {
+        *self.stop.borrow()
+    }
+
+    fn set_state(&mut self, state: State) {
+        println!("Door {state}");
+        self.status.send_replace(DoorStatus::new(state, self.clock.now()));
+    }
+
+    /// Latches the door into [`State::Fault`] and alerts whoever looks after it, passing `error` on to the caller
+    fn fault(&mut self, error: DoorError) -> DoorError {
+        println!("Door fault: {error}");
+        notification::fault(&error.to_string());
+        self.status.send_replace(DoorStatus::fault(error.to_string(), self.clock.now()));
+        error
+    }
+
+    /// Closes the door. From an unknown position without a closed switch it first opens to the open switch, since
+    /// the timed close only brings the door down from fully open.
+    pub async fn close(&mut self) -> Result<(), DoorError> {
+        if self.state() == State::Unknown && !self.actuator.has_closed_switch() {
+            println!("Door position unknown, homing to the open limit switch before closing");
+            self.open().await?;
+        }
+        match self.state() {
+            State::Open | State::Unknown => {
+                self.set_state(State::Closing);
+                let result = self.close_sequence().await;
+                let result = self.clear_obstruction(result, Direction::Close).await;
+                self.settle(result, State::Closed)?;
                 println!("Finished close routine");
             },
             State::Closed => println!("Door already closed"),
-            _ => println!("Door in flight"),
-        },
-        Err(_) => println!("Could not aquire state lock, not closing"),
-    }
-}
-
-pub fn open() {
-    use rppal::gpio::{Gpio, Trigger};
-    use std::time::Duration;
-    use std::thread;
-
-    match DOOR_STATE.lock() {
-        Ok(mut guard) => match *guard {
-            State::Closed => {
-                let gpio = Gpio::new().expect("failed to open gpio interface");
-                let mut limit_pin = gpio.get(LIMIT_PIN).expect("failed to get limit switch pin").into_input_pullup();
-                let mut mff_pin = gpio.get(MOTOR_FLIP_FLOP_PIN).expect("failed to get motor flip flop pin").into_output();
-                let mut me_pin = gpio.get(MOTOR_ENABLE_PIN).expect("failed to get motor enable pin").into_output();
-                mff_pin.set_reset_on_drop(false);
-                me_pin.set_reset_on_drop(false);
-                limit_pin.set_interrupt(Trigger::Both, Some(Duration::from_millis(10)));
-    
-                me_pin.set_low();
-                println!("Sleeping for {MFF_SAFETY_MSECS} milliseconds");
-                thread::sleep(Duration::from_millis(MFF_SAFETY_MSECS));
-                mff_pin.set_low();
-                me_pin.set_high();
-                println!("Waiting for switch interrupt (timout {OPEN_TIMEOUT_SECS} seconds)");
-                match limit_pin.poll_interrupt(true, Some(Duration::from_secs(OPEN_TIMEOUT_SECS))) {
-                    Ok(None) => println!("Timeout reached, switch was not hit"),
-                    Ok(Some(_)) => println!("Limit switch hit, door opened"),
-                    _ => println!("Error waiting for interrupt"),
-                }
-                me_pin.set_low();
-                println!("Sleeping for {MFF_SAFETY_MSECS} milliseconds");
-                thread::sleep(Duration::from_millis(MFF_SAFETY_MSECS));
-                mff_pin.set_high();
-                me_pin.set_high();
-                println!("Sleeping for 1/10 second");
-                thread::sleep(Duration::from_millis(50));
-                me_pin.set_low();
-                println!("Sleeping for {MFF_SAFETY_MSECS} milliseconds");
-                thread::sleep(Duration::from_millis(MFF_SAFETY_MSECS));
-                mff_pin.set_low();
-                *guard = State::Open;
+            State::Fault => return Err(DoorError::Faulted),
+            State::Stopped => return Err(DoorError::Stopped),
+            State::Opening | State::Closing => println!("Door in flight"),
+        }
+        Ok(())
+    }
+
+    pub async fn open(&mut self) -> Result<(), DoorError> {
+        match self.state() {
+            State::Closed | State::Unknown => {
+                self.set_state(State::Opening);
+                let result = self.open_with_retries().await;
+                self.settle(result, State::Open)?;
                 println!("Finished open routine");
             },
             State::Open => println!("Door already open"),
-            _ => println!("Door in flight"),
+            State::Fault => return Err(DoorError::Faulted),
+            State::Stopped => return Err(DoorError::Stopped),
+            State::Opening | State::Closing => println!("Door in flight"),
+        }
+        Ok(())
+    }
+
+    /// Cuts the motor and latches the door in [`State::Stopped`]. A faulted door keeps its fault, so what went
+    /// wrong is still shown until it is reset.
+    pub fn halt(&mut self) {
+        self.actuator.set_motor_enabled(false);
+        if self.state() != State::Fault {
+            self.set_state(State::Stopped);
+        }
+    }
+
+    /// Clears a stop or fault once a human has checked the door. Its position is unknown afterwards.
+    pub fn reset(&mut self) {
+        match self.state() {
+            State::Stopped | State::Fault => {
+                self.stop.send_replace(false);
+                self.set_state(State::Unknown);
+            },
+            _ => println!("Door not stopped or faulted, nothing to reset"),
+        }
+    }
+
+    async fn close_sequence(&mut self) -> Result<(), DoorError> {
+        let MotionTimings { door_close_secs, mff_safety_msecs, close_timeout_secs, .. } = self.timings;
+        self.actuator.set_motor_enabled(false);
+        println!("Sleeping for {mff_safety_msecs} milliseconds");
+        self.pause(Duration::from_millis(mff_safety_msecs)).await?;
+        if self.actuator.has_closed_switch() {
+            println!("Waiting for closed limit switch (timout {close_timeout_secs} seconds)");
+            if !self.drive(Direction::Close, Duration::from_secs(close_timeout_secs)).await? {
+                return Err(DoorError::ClosedSwitchNotReached);
+            }
+        } else {
+            println!("Closing for {door_close_secs} seconds");
+            self.drive(Direction::Close, Duration::from_secs(door_close_secs)).await?;
+        }
+        self.actuator.set_motor_enabled(false);
+        self.actuator.set_direction(Direction::Open);
+        Ok(())
+    }
+
+    async fn open_sequence(&mut self) -> Result<(), DoorError> {
+        let MotionTimings { mff_safety_msecs, open_timeout_secs, open_backoff_msecs, .. } = self.timings;
+        self.actuator.set_motor_enabled(false);
+        println!("Sleeping for {mff_safety_msecs} milliseconds");
+        self.pause(Duration::from_millis(mff_safety_msecs)).await?;
+        println!("Waiting for limit switch (timout {open_timeout_secs} seconds)");
+        if !self.drive(Direction::Open, Duration::from_secs(open_timeout_secs)).await? {
+            println!("Timeout reached, switch was not hit");
+            self.actuator.set_motor_enabled(false);
+            return Err(DoorError::OpenSwitchNotReached { attempts: 1 });
+        }
+        println!("Limit switch hit, door opened");
+        self.actuator.set_motor_enabled(false);
+        println!("Sleeping for {mff_safety_msecs} milliseconds");
+        self.pause(Duration::from_millis(mff_safety_msecs)).await?;
+        // Back off the limit switch briefly to take tension off the line
+        println!("Backing off for {open_backoff_msecs} milliseconds");
+        self.drive(Direction::Close, Duration::from_millis(open_backoff_msecs)).await?;
+        self.actuator.set_motor_enabled(false);
+        println!("Sleeping for {mff_safety_msecs} milliseconds");
+        self.pause(Duration::from_millis(mff_safety_msecs)).await?;
+        self.actuator.set_direction(Direction::Open);
+        Ok(())
+    }
+
+    /// Opens until the limit switch is hit, trying again up to `open_retries` times if it is not
+    async fn open_with_retries(&mut self) -> Result<(), DoorError> {
+        let retries = self.timings.open_retries;
+        let mut attempts = 1;
+        loop {
+            let result = self.open_sequence().await;
+            match self.clear_obstruction(result, Direction::Open).await {
+                Err(DoorError::OpenSwitchNotReached { .. }) if attempts <= retries => {
+                    println!("Retrying open, attempt {} of {}", attempts + 1, retries + 1);
+                    attempts += 1;
+                },
+                Err(DoorError::OpenSwitchNotReached { .. }) => return Err(DoorError::OpenSwitchNotReached { attempts }),
+                result => return result,
+            }
+        }
+    }
+
+    /// Backs the door away from an obstruction found while moving in `direction`, then passes the
+    /// obstruction on so the door is faulted until someone has checked the doorway
+    async fn clear_obstruction(&mut self, result: Result<(), DoorError>, direction: Direction) -> Result<(), DoorError> {
+        let Err(DoorError::Obstructed(current)) = result else {
+            return result;
+        };
+        let MotionTimings { mff_safety_msecs, obstruction_reverse_msecs, .. } = self.timings;
+        println!("Obstruction detected, motor drawing {current:.2} A");
+        record_obstruction(ObstructionEvent {
+            at: self.clock.now(),
+            during: self.state(),
+            current,
+        });
+        self.actuator.set_motor_enabled(false);
+        self.pause(Duration::from_millis(mff_safety_msecs)).await?;
+        let reverse = match direction {
+            Direction::Open => Direction::Close,
+            Direction::Close => Direction::Open,
+        };
+        println!("Reversing for {obstruction_reverse_msecs} milliseconds");
+        self.drive(reverse, Duration::from_millis(obstruction_reverse_msecs)).await?;
+        self.actuator.set_motor_enabled(false);
+        self.pause(Duration::from_millis(mff_safety_msecs)).await?;
+        self.actuator.set_direction(Direction::Open);
+        Err(DoorError::Obstructed(current))
+    }
+
+    /// Moves the door into `target` after a successful motion sequence, otherwise makes sure the motor is off
+    /// and latches the reason it stopped
+    fn settle(&mut self, result: Result<(), DoorError>, target: State) -> Result<(), DoorError> {
+        match result {
+            Ok(()) => {
+                self.set_state(target);
+                Ok(())
+            },
+            Err(DoorError::Stopped) => {
+                self.halt();
+                Err(DoorError::Stopped)
+            },
+            Err(e) => {
+                self.actuator.set_motor_enabled(false);
+                Err(self.fault(e))
+            },
+        }
+    }
+
+    /// Establishes the door's position after a restart, using the last recorded state and the limit switches.
+    /// Only a door that had settled closed is trusted to still be closed, since one that was moving, stopped or
+    /// faulted may have been left anywhere.
+    pub async fn home(&mut self, last_state: Option<State>) -> Result<(), DoorError> {
+        if self.switch_settled(Direction::Open).await? {
+            println!("Open limit switch hit, door is open");
+            self.set_state(State::Open);
+            return Ok(());
+        }
+        if self.switch_settled(Direction::Close).await? {
+            println!("Closed limit switch hit, door is closed");
+            self.set_state(State::Closed);
+            return Ok(());
+        }
+        match last_state {
+            // Without a closed switch, trust that the door has not moved since it was closed
+            Some(State::Closed) if !self.actuator.has_closed_switch() => self.set_state(State::Closed),
+            _ => {
+                println!("Door position unknown, homing to the open limit switch");
+                self.set_state(State::Unknown);
+                self.open().await?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Reads the limit switch at the `direction` end of travel, hit only if it stays hit for every debounce poll
+    async fn switch_settled(&mut self, direction: Direction) -> Result<bool, DoorError> {
+        for poll in 0..LIMIT_DEBOUNCE_POLLS {
+            if poll > 0 {
+                self.clock.sleep(Duration::from_millis(LIMIT_POLL_MSECS)).await;
+            }
+            if !self.switch_hit(direction)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn switch_hit(&mut self, direction: Direction) -> Result<bool, DoorError> {
+        let hit = match direction {
+            Direction::Open => self.actuator.open_switch_hit()?,
+            Direction::Close => self.actuator.closed_switch_hit()?,
+        };
+        Ok(hit)
+    }
+
+    /// Sleeps for `duration`, cut short if a stop is requested
+    async fn pause(&mut self, duration: Duration) -> Result<(), DoorError> {
+        let mut stop = self.stop.subscribe();
+        tokio::select! {
+            _ = self.clock.sleep(duration) => Ok(()),
+            _ = async { stop.wait_for(|stopped| *stopped).await.is_ok() } => Err(DoorError::Stopped),
+        }
+    }
+
+    /// Runs the motor in `direction` until the limit switch at that end of travel is hit, returning `false`
+    /// if `timeout` passes first. Without a switch at that end the motor simply runs for `timeout`. The switch
+    /// counts as hit once it has read hit for [`LIMIT_DEBOUNCE_POLLS`] polls in a row.
+    /// Motor current is watched once the inrush has passed.
+    async fn drive(&mut self, direction: Direction, timeout: Duration) -> Result<bool, DoorError> {
+        let start = self.clock.instant();
+        let deadline = start + timeout;
+        let inrush_end = start + Duration::from_millis(self.timings.inrush_msecs);
+        let has_switch = match direction {
+            Direction::Open => true,
+            Direction::Close => self.actuator.has_closed_switch(),
+        };
+        let mut hits = 0;
+        self.actuator.set_direction(direction);
+        self.actuator.set_motor_enabled(true);
+        while self.clock.instant() < deadline {
+            if self.stop_requested() {
+                return Err(DoorError::Stopped);
+            }
+            if self.clock.instant() >= inrush_end {
+                if let Some(current) = self.actuator.motor_overloaded()? {
+                    return Err(DoorError::Obstructed(current));
+                }
+            }
+            hits = if self.switch_hit(direction)? { hits + 1 } else { 0 };
+            if has_switch && hits >= LIMIT_DEBOUNCE_POLLS {
+                return Ok(true);
+            }
+            self.clock.sleep(Duration::from_millis(LIMIT_POLL_MSECS)).await;
+        }
+        Ok(false)
+    }
+}
+
+/// A door task started by [`DoorHandle::spawn`], along with the manual hold keeping the scheduler off it
+pub struct DoorHandle {
+    commands: UnboundedSender<(CommandId, Command)>,
+    status: watch::Receiver<DoorStatus>,
+    stop: Arc<watch::Sender<bool>>,
+    clock: Arc<dyn Clock>,
+    /// Homing and queued commands the door task has not finished yet
+    pending: Arc<watch::Sender<usize>>,
+    manual_hold: Mutex<Option<ManualHold>>,
+}
+
+impl DoorHandle {
+    /// Homes `door` and runs the commands queued through the handle against it, on a task of its own. Must be
+    /// called from within the tokio runtime.
+    pub fn spawn<A: DoorActuator + Send + 'static>(door: Door<A>, last_state: Option<State>) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let pending = Arc::new(watch::Sender::new(1));
+        let handle = Self {
+            commands,
+            status: door.subscribe(),
+            stop: door.stop_handle(),
+            clock: door.clock.clone(),
+            pending: pending.clone(),
+            manual_hold: Mutex::new(None),
+        };
+        tokio::spawn(run(door, last_state, receiver, pending));
+        handle
+    }
+
+    /// The clock the door is timed by
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    /// Queues a command for the door task, returning without waiting for the door to move
+    pub fn command(&self, command: Command) -> Result<CommandId, DoorError> {
+        let id = NEXT_COMMAND_ID.fetch_add(1, Ordering::Relaxed);
+        set_progress(id, CommandProgress::Queued);
+        self.pending.send_modify(|pending| *pending += 1);
+        if self.commands.send((id, command)).is_err() {
+            self.pending.send_modify(|pending| *pending -= 1);
+            return Err(DoorError::TaskStopped);
+        }
+        Ok(id)
+    }
+
+    /// Queues a command from a person and keeps the scheduler from undoing it for `duration`. Unlike
+    /// [`manual_command`] it does not check for lockdown.
+    pub fn manual_command(&self, command: Command, duration: HoldDuration) -> Result<CommandId, DoorError> {
+        let id = self.command(command)?;
+        match self.manual_hold.lock() {
+            Ok(mut guard) => *guard = Some(ManualHold::new(command, duration, self.clock.now())),
+            Err(_) => println!("Could not aquire hold lock, command {id} is not held"),
+        }
+        Ok(id)
+    }
+
+    /// The manual hold in force at `now`, if any. A hold that has run out is cleared.
+    pub fn manual_hold(&self, now: DateTime<Utc>) -> Option<ManualHold> {
+        let mut guard = self.manual_hold.lock().ok()?;
+        if guard.as_ref().is_some_and(|hold| hold.expired(now)) {
+            println!("Manual hold expired");
+            *guard = None;
+        }
+        guard.clone()
+    }
+
+    /// Hands the door back to the scheduler
+    pub fn cancel_hold(&self) {
+        if let Ok(mut guard) = self.manual_hold.lock() {
+            if guard.take().is_some() {
+                println!("Manual hold cancelled");
+            }
+        }
+    }
+
+    /// Ends a hold lasting until the next transition, called by the scheduler when its command changes
+    pub fn end_transition_hold(&self) {
+        if let Ok(mut guard) = self.manual_hold.lock() {
+            if guard.as_ref().is_some_and(|hold| hold.duration == HoldDuration::UntilNextTransition) {
+                println!("Schedule changed, manual hold ended");
+                *guard = None;
+            }
+        }
+    }
+
+    /// Aborts any motion immediately and holds the door until it is reset
+    pub fn stop(&self) {
+        self.stop.send_replace(true);
+    }
+
+    pub fn status(&self) -> DoorStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Waits until the door has homed and finished every command queued so far
+    pub async fn idle(&self) {
+        let mut pending = self.pending.subscribe();
+        // The handle keeps the sender alive, so this only returns once nothing is pending
+        let _ = pending.wait_for(|pending| *pending == 0).await;
+    }
+}
+
+/// Homes the door, then runs commands against it one at a time, in the order they were queued, counting each
+/// off `pending` once done.
+/// A stop requested while the door is idle latches it just like one requested mid-travel, unless it is faulted.
+async fn run<A: DoorActuator>(
+    mut door: Door<A>,
+    last_state: Option<State>,
+    mut commands: UnboundedReceiver<(CommandId, Command)>,
+    pending: Arc<watch::Sender<usize>>,
+) {
+    if let Err(e) = door.home(last_state).await {
+        println!("Could not home door: {e}");
+    }
+    pending.send_modify(|pending| *pending -= 1);
+    let mut stop = door.stop.subscribe();
+    loop {
+        let idle = !matches!(door.state(), State::Stopped | State::Fault);
+        let command = tokio::select! {
+            command = commands.recv() => command,
+            _ = async { stop.wait_for(|stopped| *stopped).await.is_ok() }, if idle => {
+                door.halt();
+                continue;
+            },
+        };
+        let Some((id, command)) = command else {
+            break;
+        };
+        set_progress(id, CommandProgress::Running);
+        let result = match command {
+            Command::Open => door.open().await,
+            Command::Close => door.close().await,
+            Command::Reset => {
+                door.reset();
+                Ok(())
+            },
+        };
+        match result {
+            Ok(()) => set_progress(id, CommandProgress::Completed),
+            Err(e) => set_progress(id, CommandProgress::Failed(e.to_string())),
+        }
+        pending.send_modify(|pending| *pending -= 1);
+    }
+}
+
+/// Records every state of the door, including while it moves, so it can be homed after a restart
+async fn persist_state(mut status: watch::Receiver<DoorStatus>) {
+    while status.changed().await.is_ok() {
+        let state = status.borrow_and_update().state;
+        if let Err(e) = save_state(state) {
+            println!("Could not save door state: {e}");
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedState {
+    state: State,
+}
+
+fn load_state() -> Result<Option<State>, StateIOError> {
+    use std::fs::read_to_string;
+    use std::path::Path;
+    if !Path::new(STATE_FILE).exists() {
+        return Ok(None);
+    }
+    let state_str = read_to_string(STATE_FILE)?;
+    let persisted: PersistedState = toml::from_str(state_str.as_str())?;
+    Ok(Some(persisted.state))
+}
+
+fn save_state(state: State) -> Result<(), StateIOError> {
+    use std::fs::write;
+    let state_str = toml::to_string_pretty(&PersistedState { state })?;
+    Ok(write(STATE_FILE, state_str)?)
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PersistedOneShots {
+    /// Id for the next command added. Ids are never reused, so cancelling a command that has already gone
+    /// cannot cancel a newer one.
+    #[serde(default)]
+    next_id: u64,
+    #[serde(default)]
+    commands: Vec<OneShotCommand>,
+}
+
+/// One-shot commands kept in a file of their own, `one_shots.toml` unless given another
+#[derive(Debug, Clone)]
+pub struct OneShots {
+    file: PathBuf,
+}
+
+impl Default for OneShots {
+    fn default() -> Self {
+        Self::new(ONE_SHOT_FILE)
+    }
+}
+
+impl OneShots {
+    pub fn new(file: impl Into<PathBuf>) -> Self {
+        Self { file: file.into() }
+    }
+
+    /// Pending one-shot commands, soonest first
+    pub fn pending(&self) -> Result<Vec<OneShotCommand>, OneShotIOError> {
+        let _guard = ONE_SHOTS.lock().map_err(|_| OneShotIOError::Lock)?;
+        let mut commands = self.load()?.commands;
+        commands.sort_by_key(|one_shot| one_shot.at);
+        Ok(commands)
+    }
+
+    /// Queues `command` to run at `at`, a wall clock time in `zone` that must still be to come at `now`
+    pub fn add(
+        &self,
+        now: DateTime<Utc>,
+        zone: ScheduleZone,
+        at: NaiveDateTime,
+        command: Command,
+        note: String,
+    ) -> Result<u64, OneShotIOError> {
+        if zone.instant(at) <= now {
+            return Err(OneShotError::InPast(at).into());
+        }
+        let _guard = ONE_SHOTS.lock().map_err(|_| OneShotIOError::Lock)?;
+        let mut one_shots = self.load()?;
+        let id = one_shots.next_id;
+        one_shots.next_id += 1;
+        one_shots.commands.push(OneShotCommand { id, at, command, note });
+        self.save(&one_shots)?;
+        Ok(id)
+    }
+
+    pub fn cancel(&self, id: u64) -> Result<(), OneShotIOError> {
+        let _guard = ONE_SHOTS.lock().map_err(|_| OneShotIOError::Lock)?;
+        let mut one_shots = self.load()?;
+        one_shots.commands.retain(|one_shot| one_shot.id != id);
+        self.save(&one_shots)
+    }
+
+    /// Removes and returns the one-shot commands due at `now`, reading their times as wall clock times in
+    /// `zone`. Oldest first, dropping any too overdue to run.
+    pub fn take_due(&self, now: DateTime<Utc>, zone: ScheduleZone) -> Result<Vec<OneShotCommand>, OneShotIOError> {
+        let _guard = ONE_SHOTS.lock().map_err(|_| OneShotIOError::Lock)?;
+        let one_shots = self.load()?;
+        let due_at = |one_shot: &OneShotCommand| zone.instant(one_shot.at);
+        if one_shots.commands.iter().all(|one_shot| due_at(one_shot) > now) {
+            return Ok(Vec::new());
+        }
+        let (mut due, pending): (Vec<_>, Vec<_>) =
+            one_shots.commands.into_iter().partition(|one_shot| due_at(one_shot) <= now);
+        self.save(&PersistedOneShots {
+            next_id: one_shots.next_id,
+            commands: pending,
+        })?;
+        due.sort_by_key(|one_shot| one_shot.at);
+        due.retain(|one_shot| {
+            let fresh = now - due_at(one_shot) <= TimeDelta::minutes(ONE_SHOT_GRACE_MINS);
+            if !fresh {
+                println!("Dropping one-shot {:?} due at {}, too late to run", one_shot.command, one_shot.at);
+            }
+            fresh
+        });
+        Ok(due)
+    }
+
+    fn load(&self) -> Result<PersistedOneShots, OneShotIOError> {
+        use std::fs::read_to_string;
+        if !self.file.exists() {
+            return Ok(PersistedOneShots::default());
+        }
+        let one_shots_str = read_to_string(&self.file)?;
+        let mut persisted: PersistedOneShots = toml::from_str(one_shots_str.as_str())?;
+        // Files written before ids were tracked only know the ids still pending
+        let unused = persisted.commands.iter().map(|one_shot| one_shot.id + 1).max().unwrap_or(0);
+        persisted.next_id = persisted.next_id.max(unused);
+        Ok(persisted)
+    }
+
+    fn save(&self, one_shots: &PersistedOneShots) -> Result<(), OneShotIOError> {
+        use std::fs::write;
+        let one_shots_str = toml::to_string_pretty(one_shots)?;
+        Ok(write(&self.file, one_shots_str)?)
+    }
+}
+
+fn set_progress(id: CommandId, progress: CommandProgress) {
+    match COMMAND_PROGRESS.lock() {
+        Ok(mut guard) => {
+            guard.insert(id, progress);
+            while guard.len() > COMMAND_HISTORY {
+                guard.pop_first();
+            }
+        },
+        Err(_) => println!("Could not aquire progress lock, not recording progress of command {id}"),
+    }
+}
+
+fn record_obstruction(event: ObstructionEvent) {
+    match OBSTRUCTIONS.lock() {
+        Ok(mut guard) => {
+            guard.push_back(event);
+            while guard.len() > OBSTRUCTION_HISTORY {
+                guard.pop_front();
+            }
         },
-        Err(_) => println!("Could not aquire state lock, not opening"),
+        Err(_) => println!("Could not aquire obstruction lock, not recording obstruction"),
+    }
+}
+
+/// Starts the task that owns the door hardware. Must be called from within the tokio runtime.
+pub fn init(actuator: impl DoorActuator + Send + 'static, timings: MotionTimings, clock: Arc<dyn Clock>) {
+    if DOOR.get().is_some() {
+        println!("Door already initialized, actuator not installed");
+        return;
+    }
+    let last_state = load_state().unwrap_or_else(|e| {
+        println!("Could not load door state: {e}");
+        None
+    });
+    let door = Door::new(actuator, timings, clock, State::Unknown);
+    tokio::spawn(persist_state(door.subscribe()));
+    if DOOR.set(Arc::new(DoorHandle::spawn(door, last_state))).is_err() {
+        println!("Door already initialized");
+    }
+}
+
+/// The door started by [`init`]
+pub fn handle() -> Result<Arc<DoorHandle>, DoorError> {
+    DOOR.get().cloned().ok_or(DoorError::NotInitialized)
+}
+
+/// Installs the sensor read by [`light_level`]
+pub fn init_light_sensor(sensor: Box<dyn LightSensor + Send>) {
+    match LIGHT_SENSOR.lock() {
+        Ok(mut guard) => *guard = Some(sensor),
+        Err(_) => println!("Could not aquire light sensor lock, sensor not installed"),
+    }
+}
+
+/// Replaces the door motor, limit switch and light sensor with [`crate::simulation`] models
+pub fn simulate(config: SimulationConfig, timings: MotionTimings, clock: Arc<dyn Clock>) {
+    init(SimulatedActuator::new(config.clone(), clock.clone()), timings, clock.clone());
+    init_light_sensor(Box::new(SimulatedLightSensor::new(config, clock)));
+}
+
+/// The sensor installed by [`init_light_sensor`], read through [`light_level`] and [`light_unit`]
+pub struct InstalledLightSensor;
+
+impl LightSensor for InstalledLightSensor {
+    fn light_level(&mut self) -> Result<f64, LightLevelError> {
+        light_level()
+    }
+
+    fn unit(&self) -> LightUnit {
+        light_unit()
+    }
+}
+
+/// The door's clock, the system clock until [`init`] installs one
+pub fn clock() -> Arc<dyn Clock> {
+    DOOR.get().map_or_else(|| Arc::new(SystemClock) as Arc<dyn Clock>, |door| door.clock())
+}
+
+/// Time on the door's clock, the system clock until [`init`] installs one
+pub fn now() -> DateTime<Utc> {
+    DOOR.get().map_or_else(Utc::now, |door| door.clock.now())
+}
+
+/// Queues a command for the door task, returning without waiting for the door to move
+pub fn command(command: Command) -> Result<CommandId, DoorError> {
+    handle()?.command(command)
+}
+
+pub fn close() -> Result<CommandId, DoorError> {
+    command(Command::Close)
+}
+
+pub fn open() -> Result<CommandId, DoorError> {
+    command(Command::Open)
+}
+
+/// Queues a command from a person and keeps the scheduler from undoing it for `duration`. Opening is refused
+/// during lockdown.
+pub fn manual_command(command: Command, duration: HoldDuration) -> Result<CommandId, DoorError> {
+    if command == Command::Open && get_settings()?.lockdown {
+        return Err(DoorError::LockedDown);
+    }
+    handle()?.manual_command(command, duration)
+}
+
+/// The manual hold in force at `now`, if any. A hold that has run out is cleared.
+pub fn manual_hold(now: DateTime<Utc>) -> Option<ManualHold> {
+    DOOR.get()?.manual_hold(now)
+}
+
+/// Hands the door back to the scheduler
+pub fn cancel_hold() {
+    if let Some(door) = DOOR.get() {
+        door.cancel_hold();
+    }
+}
+
+/// Pending one-shot commands in `one_shots.toml`, soonest first
+pub fn one_shots() -> Result<Vec<OneShotCommand>, OneShotIOError> {
+    OneShots::default().pending()
+}
+
+/// Queues `command` in `one_shots.toml` to run at `at`, a wall clock time in `zone` that must still be to come
+/// at `now`
+pub fn add_one_shot(
+    now: DateTime<Utc>,
+    zone: ScheduleZone,
+    at: NaiveDateTime,
+    command: Command,
+    note: String,
+) -> Result<u64, OneShotIOError> {
+    OneShots::default().add(now, zone, at, command, note)
+}
+
+pub fn cancel_one_shot(id: u64) -> Result<(), OneShotIOError> {
+    OneShots::default().cancel(id)
+}
+
+/// Aborts any motion immediately and holds the door until [`reset`] is called
+pub fn stop() -> Result<(), DoorError> {
+    handle()?.stop();
+    Ok(())
+}
+
+pub fn reset() -> Result<CommandId, DoorError> {
+    command(Command::Reset)
+}
+
+pub fn status() -> Result<DoorStatus, DoorError> {
+    Ok(handle()?.status())
+}
+
+pub fn command_progress(id: CommandId) -> CommandProgress {
+    match COMMAND_PROGRESS.lock() {
+        Ok(guard) => guard.get(&id).cloned().unwrap_or(CommandProgress::Unknown),
+        Err(_) => CommandProgress::Unknown,
+    }
+}
+
+/// Recent obstructions, oldest first
+pub fn obstructions() -> Vec<ObstructionEvent> {
+    match OBSTRUCTIONS.lock() {
+        Ok(guard) => guard.iter().cloned().collect(),
+        Err(_) => Vec::new(),
     }
 }
 
@@ -98,57 +811,114 @@ pub fn get_settings() -> Result<Settings, SettingsIOError> {
     let settings: Settings;
     if settings_file.exists() {
         let settings_str = read_to_string("settings.toml")?;
-        settings = toml::from_str(settings_str.as_str())?;
+        let mut table: toml::Table = toml::from_str(settings_str.as_str())?;
+        let version = migration::migrate(&mut table)?;
+        settings = table.try_into()?;
+        if version < SETTINGS_VERSION {
+            let backup = format!("settings.toml.v{version}.bak");
+            std::fs::copy("settings.toml", &backup)?;
+            write_settings(settings.clone())?;
+            println!("Upgraded settings.toml from version {version}, original kept as {backup}");
+        }
     } else {
         settings = Settings::default();
     }
-    return Ok(settings);
+    Ok(settings)
 }
 
 pub fn write_settings(settings: Settings) -> Result<(), SettingsIOError> {
     use std::fs::write;
     use toml;
+    let settings = Settings {
+        version: SETTINGS_VERSION,
+        ..settings
+    };
     let settings_str = toml::to_string_pretty(&settings)?;
-    return Ok(write("./settings.toml", settings_str)?);
+    Ok(write("./settings.toml", settings_str)?)
+}
+
+/// Turns lockdown on or off, leaving the rest of the settings alone
+pub fn set_lockdown(lockdown: bool) -> Result<(), SettingsIOError> {
+    let mut settings = get_settings()?;
+    settings.lockdown = lockdown;
+    println!("Lockdown {}", if lockdown { "on" } else { "off" });
+    write_settings(settings)
 }
 
 pub fn light_level() -> Result<f64, LightLevelError> {
-    use rppal::spi::{Bus, Mode, Segment, SlaveSelect, Spi};
+    match LIGHT_SENSOR.lock() {
+        Ok(mut guard) => match guard.as_mut() {
+            Some(sensor) => sensor.light_level(),
+            None => Err(LightLevelError::NotConfigured),
+        },
+        Err(_) => Err(LightLevelError::NotConfigured),
+    }
+}
 
-    let mut spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, 1_000_000, Mode::Mode0)?;
-    let write_buffer: [u8; 3] = [6, 0, 0];
-    let mut read_buffer = [0u8; 5];
-    
-    spi.transfer(&mut read_buffer, &write_buffer);
-    
-    let mut result: u32 = 0;
-    for (i, byte) in read_buffer.iter().enumerate() {
-        result |= (u32::from(*byte) << (u32::from(2 - i as u8) * 8)) as u32;
+/// Unit the installed light sensor reports in
+pub fn light_unit() -> LightUnit {
+    match LIGHT_SENSOR.lock() {
+        Ok(guard) => guard.as_ref().map_or(LightUnit::default(), |sensor| sensor.unit()),
+        Err(_) => LightUnit::default(),
     }
-    let result = (1.0 - ((result as f64)/4096.0)) * 100.0;
-    dbg!(&result);
-    return Ok(result);
 }
 
 #[derive(Error, Debug)]
-pub enum SettingsIOError {
-    #[error("could not access settings.toml")]
+pub enum DoorError {
+    #[error("door hardware not initialized")]
+    NotInitialized,
+    #[error("door task is not running")]
+    TaskStopped,
+    #[error("door is faulted")]
+    Faulted,
+    #[error("door was stopped")]
+    Stopped,
+    #[error("door hardware failed")]
+    Actuator(#[from] ActuatorError),
+    #[error("door did not reach the open limit switch after {attempts} attempts")]
+    OpenSwitchNotReached { attempts: u32 },
+    #[error("door did not reach the closed limit switch")]
+    ClosedSwitchNotReached,
+    #[error("door obstructed, motor drew {0:.2} A")]
+    Obstructed(f64),
+    #[error("door is locked down, end the lockdown to open it")]
+    LockedDown,
+    #[error("could not read settings")]
+    Settings(#[from] SettingsIOError),
+}
+
+#[derive(Error, Debug)]
+pub enum StateIOError {
+    #[error("could not access door_state.toml")]
     FileAccess(#[from] std::io::Error),
-    #[error("could not serialize settings")]
+    #[error("could not serialize door state")]
     Serialize(#[from] toml::ser::Error),
-    #[error("could deserialize settings.toml")]
+    #[error("could not deserialize door_state.toml")]
     Deserialize(#[from] toml::de::Error),
 }
 
 #[derive(Error, Debug)]
-pub enum LightLevelError {
-    #[error("could not access MCP3208")]
-    SPI(#[from] rppal::spi::Error),
+pub enum OneShotIOError {
+    #[error("could not access one_shots.toml")]
+    FileAccess(#[from] std::io::Error),
+    #[error("could not serialize one-shot commands")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("could not deserialize one_shots.toml")]
+    Deserialize(#[from] toml::de::Error),
+    #[error("could not aquire one-shot lock")]
+    Lock,
+    #[error(transparent)]
+    Rejected(#[from] OneShotError),
 }
 
-enum State {
-    Open,
-    Opening,
-    Closed,
-    Closing,
+#[derive(Error, Debug)]
+pub enum SettingsIOError {
+    #[error("could not access settings.toml")]
+    FileAccess(#[from] std::io::Error),
+    #[error("could not serialize settings")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("could deserialize settings.toml")]
+    Deserialize(#[from] toml::de::Error),
+    #[error("could not upgrade settings.toml: {0}")]
+    Migration(#[from] MigrationError),
 }
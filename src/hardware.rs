@@ -0,0 +1,175 @@
+use crate::notification::NotificationConfig;
+use crate::sensor::{CurrentSensorConfig, LightSensorConfig};
+use crate::time_sync::TimeSyncConfig;
+use serde::Deserialize;
+use thiserror::Error;
+
+const HARDWARE_FILE: &str = "./hardware.toml";
+/// Highest GPIO brought out to the Raspberry Pi's 40 pin header
+const MAX_GPIO_PIN: u8 = 27;
+
+/// Wiring of the door controller, read once at startup from the `[hardware]` section of hardware.toml
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct HardwareConfig {
+    pub light_sensor: LightSensorConfig,
+    /// GPIO pin of the limit switch at the open position
+    pub limit_pin: u8,
+    /// GPIO pin selecting the motor direction
+    pub motor_flip_flop_pin: u8,
+    /// GPIO pin switching the motor on
+    pub motor_enable_pin: u8,
+    /// GPIO pin of a limit switch at the closed position, if one is fitted
+    pub closed_limit_pin: Option<u8>,
+    /// Motor current sensing used to detect obstructions, if fitted
+    pub current_sensor: Option<CurrentSensorConfig>,
+    pub timings: MotionTimings,
+    pub notifications: NotificationConfig,
+    pub time_sync: TimeSyncConfig,
+}
+
+impl Default for HardwareConfig {
+    fn default() -> Self {
+        Self {
+            light_sensor: LightSensorConfig::default(),
+            limit_pin: 24,
+            motor_flip_flop_pin: 5,
+            motor_enable_pin: 6,
+            closed_limit_pin: None,
+            current_sensor: None,
+            timings: MotionTimings::default(),
+            notifications: NotificationConfig::default(),
+            time_sync: TimeSyncConfig::default(),
+        }
+    }
+}
+
+impl HardwareConfig {
+    /// Checks every pin exists and is used only once, including the pins taken by the sensors' buses
+    pub fn validate(&self) -> Result<(), HardwareConfigError> {
+        let mut claimed: Vec<(u8, &'static str)> =
+            self.light_sensor.pins().into_iter().map(|pin| (pin, "light_sensor")).collect();
+        if let Some(current_sensor) = &self.current_sensor {
+            let channel = (current_sensor.bus, current_sensor.chip_select, current_sensor.channel);
+            if self.light_sensor.adc_channel() == Some(channel) {
+                return Err(HardwareConfigError::AdcChannelConflict(current_sensor.channel));
+            }
+            if current_sensor.threshold <= 0.0 {
+                return Err(HardwareConfigError::CurrentThreshold);
+            }
+            // Both sensors may share one SPI bus
+            for pin in current_sensor.pins() {
+                if !claimed.iter().any(|(claimed, _)| *claimed == pin) {
+                    claimed.push((pin, "current_sensor"));
+                }
+            }
+        }
+        let pins = [
+            ("limit_pin", Some(self.limit_pin)),
+            ("motor_flip_flop_pin", Some(self.motor_flip_flop_pin)),
+            ("motor_enable_pin", Some(self.motor_enable_pin)),
+            ("closed_limit_pin", self.closed_limit_pin),
+        ];
+        for (name, pin) in pins {
+            let Some(pin) = pin else {
+                continue;
+            };
+            if pin > MAX_GPIO_PIN {
+                return Err(HardwareConfigError::InvalidPin { name, pin });
+            }
+            if let Some(&(_, other)) = claimed.iter().find(|(claimed, _)| *claimed == pin) {
+                return Err(HardwareConfigError::PinConflict { pin, first: other, second: name });
+            }
+            claimed.push((pin, name));
+        }
+        self.timings.validate()
+    }
+}
+
+/// How long the door motor is driven for, how long it rests between moves and how often it retries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct MotionTimings {
+    /// Time the motor runs to close the door when there is no closed limit switch
+    pub door_close_secs: u64,
+    /// Rest with the motor off before changing direction, so the flip flop never switches under load
+    pub mff_safety_msecs: u64,
+    /// Give up on the open limit switch after this long
+    pub open_timeout_secs: u64,
+    /// Further attempts at opening when the open limit switch is not hit, before the door faults
+    pub open_retries: u32,
+    /// Give up on the closed limit switch after this long
+    pub close_timeout_secs: u64,
+    /// Reverse briefly after opening to take tension off the line
+    pub open_backoff_msecs: u64,
+    /// Ignore the motor current for this long after the motor starts, while it draws its inrush current
+    pub inrush_msecs: u64,
+    /// How far to back the door away from an obstruction
+    pub obstruction_reverse_msecs: u64,
+}
+
+impl Default for MotionTimings {
+    fn default() -> Self {
+        Self {
+            door_close_secs: 5,
+            mff_safety_msecs: 250,
+            open_timeout_secs: 6,
+            open_retries: 2,
+            close_timeout_secs: 6,
+            open_backoff_msecs: 50,
+            inrush_msecs: 300,
+            obstruction_reverse_msecs: 1000,
+        }
+    }
+}
+
+impl MotionTimings {
+    fn validate(&self) -> Result<(), HardwareConfigError> {
+        let timings = [
+            ("door_close_secs", self.door_close_secs),
+            ("mff_safety_msecs", self.mff_safety_msecs),
+            ("open_timeout_secs", self.open_timeout_secs),
+            ("close_timeout_secs", self.close_timeout_secs),
+        ];
+        match timings.into_iter().find(|(_, value)| *value == 0) {
+            Some((name, _)) => Err(HardwareConfigError::ZeroTiming(name)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct HardwareFile {
+    #[serde(default)]
+    hardware: HardwareConfig,
+}
+
+pub fn load() -> Result<HardwareConfig, HardwareConfigError> {
+    use std::fs::read_to_string;
+    use std::path::Path;
+    if !Path::new(HARDWARE_FILE).exists() {
+        return Ok(HardwareConfig::default());
+    }
+    let hardware_str = read_to_string(HARDWARE_FILE)?;
+    let file: HardwareFile = toml::from_str(hardware_str.as_str())?;
+    file.hardware.validate()?;
+    Ok(file.hardware)
+}
+
+#[derive(Error, Debug)]
+pub enum HardwareConfigError {
+    #[error("could not access hardware.toml")]
+    FileAccess(#[from] std::io::Error),
+    #[error("could not deserialize hardware.toml: {0}")]
+    Deserialize(#[from] toml::de::Error),
+    #[error("{name} {pin} is not a GPIO pin on the header")]
+    InvalidPin { name: &'static str, pin: u8 },
+    #[error("GPIO {pin} is used by both {first} and {second}")]
+    PinConflict { pin: u8, first: &'static str, second: &'static str },
+    #[error("light and current sensors both read ADC channel {0}")]
+    AdcChannelConflict(u8),
+    #[error("current sensor threshold must be greater than zero")]
+    CurrentThreshold,
+    #[error("{0} must be greater than zero")]
+    ZeroTiming(&'static str),
+}
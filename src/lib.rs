@@ -1,7 +1,30 @@
+#[cfg(feature = "ssr")]
+pub mod actuator;
 pub mod app;
 #[cfg(feature = "ssr")]
+pub mod clock;
+#[cfg(feature = "ssr")]
 pub mod door;
+#[cfg(feature = "ssr")]
+pub mod hardware;
+#[cfg(feature = "ssr")]
+pub mod migration;
+#[cfg(feature = "ssr")]
+pub mod notification;
+#[cfg(feature = "ssr")]
+pub mod sensor;
+#[cfg(feature = "ssr")]
+pub mod scheduler;
 pub mod settings;
+#[cfg(feature = "ssr")]
+pub mod simulation;
+pub mod status;
+#[cfg(feature = "ssr")]
+pub mod sun;
+#[cfg(feature = "ssr")]
+pub mod time_sync;
+#[cfg(feature = "ssr")]
+pub mod timezone;
 
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
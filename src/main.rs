@@ -1,5 +1,3 @@
-const POLL_STATE_SECS: u64 = 5;
-
 #[cfg(feature = "ssr")]
 #[tokio::main]
 async fn main() {
@@ -8,25 +6,56 @@ async fn main() {
     use leptos::prelude::*;
     use leptos_axum::{generate_route_list, LeptosRoutes};
     use chicken_door::app::*;
-    use chicken_door::settings::Settings;
-    use chicken_door::door::{open, close, light_level};
-    use std::time::Duration;
-    use toml;
+    use chicken_door::actuator::RppalActuator;
+    use chicken_door::clock::{Clock, SystemClock};
+    use chicken_door::door;
+    use chicken_door::hardware;
+    use chicken_door::notification;
+    use chicken_door::simulation::SimulationConfig;
+    use chicken_door::scheduler;
+    use chicken_door::time_sync;
     use notify::{Config, Event,EventKind, RecommendedWatcher, RecursiveMode, Watcher};
     use std::path::Path;
-    use std::fs::read_to_string;
-    use chrono::Local;
     use std::sync::{Arc, Mutex};
 
+    let hardware = match hardware::load() {
+        Ok(hardware) => hardware,
+        Err(e) => {
+            println!("Invalid hardware.toml: {e}");
+            std::process::exit(1);
+        },
+    };
+    notification::init(hardware.notifications.clone());
+    time_sync::init(hardware.time_sync.clone());
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    match SimulationConfig::from_args(std::env::args()) {
+        Some(config) => {
+            println!("Simulating door hardware: {config:?}");
+            door::simulate(config, hardware.timings, clock.clone());
+        },
+        None => {
+            match RppalActuator::new(&hardware) {
+                Ok(actuator) => door::init(actuator, hardware.timings, clock.clone()),
+                Err(e) => println!("Could not access door hardware: {e}"),
+            }
+            match hardware.light_sensor.build() {
+                Ok(sensor) => door::init_light_sensor(sensor),
+                Err(e) => println!("Could not access light sensor: {e}"),
+            }
+        },
+    }
+
+    // Older settings files are upgraded here, before anything else reads them
+    let settings = match door::get_settings() {
+        Ok(settings) => settings,
+        Err(e) => {
+            println!("Invalid settings.toml: {e}");
+            std::process::exit(1);
+        },
+    };
     tokio::spawn(async move {
         let settings_file = Path::new("./settings.toml");
-        let settings: Arc<Mutex<Settings>>;
-        if settings_file.exists() {
-            let settings_str = read_to_string("settings.toml").unwrap();
-            settings = Arc::new(Mutex::new(toml::from_str::<Settings>(settings_str.as_str()).unwrap()));
-        } else {
-            settings = Arc::new(Mutex::new(Settings::default()));
-        }
+        let settings = Arc::new(Mutex::new(settings));
 
         let settings_clone = settings.clone();
         
@@ -35,8 +64,10 @@ async fn main() {
                 match res {
                     Ok(Event { kind: EventKind::Modify(_), ..}) => {
                         println!("Reloading settings");
-                        let settings_str = read_to_string("settings.toml").unwrap();
-                        *settings_clone.lock().unwrap() = toml::from_str(settings_str.as_str()).unwrap();
+                        match door::get_settings() {
+                            Ok(settings) => *settings_clone.lock().unwrap() = settings,
+                            Err(e) => println!("Could not reload settings, keeping the previous ones: {e}"),
+                        }
                     },
                     Err(e) => println!("watch error: {:?}", e),
                     _ => println!("Unhandled watcher event"),
@@ -44,28 +75,11 @@ async fn main() {
             },
             Config::default(),
         ).unwrap();
-        watcher.watch(settings_file, RecursiveMode::NonRecursive);
-        
-        loop {
-            if let Ok(current_light_level) = light_level() {
-                let current_time = Local::now().time();
-                
-                let settings = settings.lock().unwrap();
-                let open_time = settings.times.open;
-                let close_time = settings.times.close;
-                let open_light_level = settings.light_levels.open;
-                let close_light_level = settings.light_levels.close;
-
-                if current_time >= close_time || current_light_level <= close_light_level {
-                    close();
-                } else if current_time >= open_time || current_light_level >= open_light_level {
-                    open();
-                }
-            }
-            // if settings.light_lel
-            println!("Sleeping {POLL_STATE_SECS} seconds");
-            tokio::time::sleep(Duration::from_secs(POLL_STATE_SECS)).await;
+        if let Err(e) = watcher.watch(settings_file, RecursiveMode::NonRecursive) {
+            println!("Could not watch settings.toml, changes will need a restart: {e}");
         }
+        
+        scheduler::run(settings).await;
     });
 
     let conf = get_configuration(Some("Cargo.toml")).unwrap();
@@ -0,0 +1,39 @@
+use crate::settings::SETTINGS_VERSION;
+use thiserror::Error;
+use toml::{Table, Value};
+
+/// Upgrades a settings.toml table written at version N to version N + 1, indexed by N
+const MIGRATIONS: [fn(&mut Table); SETTINGS_VERSION as usize] = [versioned];
+
+/// Schema version of a settings.toml table
+pub fn version(table: &Table) -> Result<u32, MigrationError> {
+    match table.get("version") {
+        None => Ok(0),
+        Some(Value::Integer(version)) => u32::try_from(*version).map_err(|_| MigrationError::InvalidVersion),
+        Some(_) => Err(MigrationError::InvalidVersion),
+    }
+}
+
+/// Brings a settings.toml table up to [`SETTINGS_VERSION`] one step at a time, returning the version it was at
+pub fn migrate(table: &mut Table) -> Result<u32, MigrationError> {
+    let from = version(table)?;
+    if from > SETTINGS_VERSION {
+        return Err(MigrationError::Newer(from));
+    }
+    for (step, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        migration(table);
+        table.insert("version".to_string(), Value::Integer(step as i64 + 1));
+    }
+    Ok(from)
+}
+
+/// Version 1 added the version itself. Every setting added before then has a default, so nothing else changes.
+fn versioned(_table: &mut Table) {}
+
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error("settings version is not a positive whole number")]
+    InvalidVersion,
+    #[error("settings are version {0}, newer than this server understands")]
+    Newer(u32),
+}
@@ -0,0 +1,33 @@
+use serde::Deserialize;
+use std::process::Command;
+use std::sync::OnceLock;
+
+static NOTIFICATIONS: OnceLock<NotificationConfig> = OnceLock::new();
+
+/// Where alerts about the door are sent, from the `[hardware.notifications]` section of hardware.toml
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// Program run with the fault reason as its only argument whenever the door faults,
+    /// e.g. a script that sends an email or posts to a push notification service
+    pub fault_command: Option<String>,
+}
+
+pub fn init(config: NotificationConfig) {
+    if NOTIFICATIONS.set(config).is_err() {
+        println!("Notifications already initialized");
+    }
+}
+
+/// Sends `reason` to every configured notification channel without waiting for delivery
+pub fn fault(reason: &str) {
+    let Some(command) = NOTIFICATIONS.get().and_then(|config| config.fault_command.clone()) else {
+        return;
+    };
+    let reason = reason.to_string();
+    std::thread::spawn(move || match Command::new(&command).arg(&reason).status() {
+        Ok(status) if status.success() => println!("Sent fault notification"),
+        Ok(status) => println!("Fault notification command {command} exited with {status}"),
+        Err(e) => println!("Could not run fault notification command {command}: {e}"),
+    });
+}
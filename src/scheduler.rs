@@ -0,0 +1,357 @@
+use crate::clock::Clock;
+use crate::door::{self, DoorHandle, InstalledLightSensor, OneShots};
+use crate::sensor::LightSensor;
+use crate::settings::{DayPlan, LightFilter, LightLevels, ScheduleMode, Settings, Times, TriggerPolicy};
+use crate::status::{Command, HoldDuration, ScheduledEvent, State};
+use crate::sun;
+use crate::time_sync;
+use crate::timezone::ScheduleZone;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const POLL_STATE_SECS: u64 = 5;
+
+/// What the scheduler should do with the door at a given moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// The door is faulted or stopped and waits for a person
+    Blocked,
+    /// The schedule calls for nothing right now
+    Idle,
+    /// The schedule calls for this command and the door is already there or on its way
+    Settled(Command),
+    /// The door needs to move
+    Move(Command),
+}
+
+impl Action {
+    /// The command the schedule calls for, whether or not the door has to move for it
+    pub fn scheduled(self) -> Option<Command> {
+        match self {
+            Self::Settled(command) | Self::Move(command) => Some(command),
+            Self::Blocked | Self::Idle => None,
+        }
+    }
+}
+
+/// Decides what to do with the door at `now`, given the light conditions from [`LightTracker`]. `now` is
+/// `None` while the clock cannot be trusted, leaving the light alone to move the door.
+///
+/// Times of day are reached at the moment the wall clock in the schedule's timezone first shows them, so a
+/// time the clocks go back over is not reached a second time.
+pub fn decide(now: Option<DateTime<Utc>>, light: LightConditions, settings: &Settings, door_state: State) -> Action {
+    if matches!(door_state, State::Fault | State::Stopped) {
+        return Action::Blocked;
+    }
+    let command = match now {
+        Some(now) => {
+            let zone = ScheduleZone::of(settings);
+            let today = zone.today(now);
+            let reached = |time: NaiveTime| now >= zone.instant(today.and_time(time));
+            command_for(settings, &day_plan(settings, today), reached, light)
+        },
+        None => light_command(settings, light),
+    };
+    match command {
+        Some(Command::Open) if matches!(door_state, State::Open | State::Opening) => Action::Settled(Command::Open),
+        Some(Command::Close) if matches!(door_state, State::Closed | State::Closing) => {
+            Action::Settled(Command::Close)
+        },
+        Some(command) => Action::Move(command),
+        None => Action::Idle,
+    }
+}
+
+/// The command the light alone calls for, when the time of day is unknown. Lockdown still keeps the door closed.
+fn light_command(settings: &Settings, light: LightConditions) -> Option<Command> {
+    if settings.lockdown || light.dark {
+        Some(Command::Close)
+    } else if light.bright {
+        Some(Command::Open)
+    } else {
+        None
+    }
+}
+
+/// Times between `from` and `to` at which the schedule will move the door whatever the light does, soonest
+/// first. Triggers that wait on the light, alone or as well as the time, cannot be foreseen and are left out.
+/// Times the clocks skip over are moved to when the clocks go forward.
+pub fn preview(settings: &Settings, from: NaiveDateTime, to: NaiveDateTime) -> Vec<ScheduledEvent> {
+    let zone = ScheduleZone::of(settings);
+    let mut events = Vec::new();
+    for date in from.date().iter_days().take_while(|date| *date <= to.date()) {
+        let DayPlan::Times(times) = day_plan(settings, date) else {
+            continue;
+        };
+        let triggers = [
+            (Command::Open, &settings.triggers.open, times.open),
+            (Command::Close, &settings.triggers.close, times.close),
+        ];
+        for (command, policy, time) in triggers {
+            let Some(time) = certain_time(policy, time) else {
+                continue;
+            };
+            let at = zone.effective(date.and_time(time));
+            if (from..=to).contains(&at) {
+                events.push(ScheduledEvent { at, command });
+            }
+        }
+    }
+    events.sort_by_key(|event| event.at);
+    events
+}
+
+/// The time a trigger fires at by the clock alone, if it ever does
+fn certain_time(policy: &TriggerPolicy, time: NaiveTime) -> Option<NaiveTime> {
+    match policy {
+        TriggerPolicy::TimeOnly | TriggerPolicy::TimeOrLight => Some(time),
+        TriggerPolicy::LightInWindow { latest, .. } => Some(*latest),
+        // Waits for the light once the time is reached, which may never come
+        TriggerPolicy::TimeAndLight | TriggerPolicy::LightOnly => None,
+    }
+}
+
+/// Moves a door as the schedule calls for, tracking what it has seen between ticks
+pub struct Scheduler {
+    door: Arc<DoorHandle>,
+    clock: Arc<dyn Clock>,
+    sensor: Box<dyn LightSensor + Send>,
+    one_shots: OneShots,
+    light: LightTracker,
+    /// Last command the schedule called for, to spot transitions that end manual holds
+    last_scheduled: Option<Command>,
+}
+
+impl Scheduler {
+    /// Schedules `door` on its own clock, by the light from `sensor` and the one-shot commands in `one_shots`
+    pub fn new(door: Arc<DoorHandle>, sensor: Box<dyn LightSensor + Send>, one_shots: OneShots) -> Self {
+        Self {
+            clock: door.clock(),
+            door,
+            sensor,
+            one_shots,
+            light: LightTracker::default(),
+            last_scheduled: None,
+        }
+    }
+
+    /// Reads the light and moves the door as the schedule, one-shot commands and manual holds call for,
+    /// returning the commands queued for it
+    pub fn tick(&mut self, settings: &Settings) -> Vec<Command> {
+        let mut queued = Vec::new();
+        let now = self.clock.now();
+        let reading = self.sensor.light_level().ok();
+        let filter = &settings.light_filter;
+        let conditions = self.light.update(filter, &settings.light_levels, self.clock.instant(), reading);
+        if let Some(Err(e)) = settings.timezone.as_deref().map(ScheduleZone::named) {
+            println!("{e}, using the system timezone");
+        }
+        let zone = ScheduleZone::of(settings);
+        let trusted_now = match time_sync::check(self.clock.as_ref()) {
+            Ok(()) => Some(now),
+            Err(e) => {
+                println!("The {e}, only the light moves the door");
+                None
+            },
+        };
+        let action = decide(trusted_now, conditions, settings, self.door.status().state);
+        if action == Action::Blocked {
+            println!("Door is faulted or stopped, skipping scheduled operations until it is reset");
+            return queued;
+        }
+        if let Some(command) = action.scheduled() {
+            if self.last_scheduled.is_some_and(|last| last != command) {
+                self.door.end_transition_hold();
+            }
+            self.last_scheduled = Some(command);
+        }
+        // One-shots take over from the schedule like a manual command would, once the clock can say they are due
+        let due = match trusted_now {
+            Some(now) => self.one_shots.take_due(now, zone),
+            None => Ok(Vec::new()),
+        };
+        match due {
+            Ok(due) => {
+                for one_shot in due {
+                    if settings.lockdown && one_shot.command != Command::Close {
+                        println!("Lockdown on, skipping one-shot {:?}", one_shot.command);
+                        continue;
+                    }
+                    println!("Running one-shot {:?} {}", one_shot.command, one_shot.note);
+                    match self.door.manual_command(one_shot.command, HoldDuration::UntilNextTransition) {
+                        Ok(_) => queued.push(one_shot.command),
+                        Err(e) => println!("Could not command door: {e}"),
+                    }
+                }
+            },
+            Err(e) => println!("Could not read one-shot commands: {e}"),
+        }
+        if settings.lockdown {
+            self.door.cancel_hold();
+        }
+        if let Some(hold) = self.door.manual_hold(now) {
+            println!("Door held {:?} by hand, not following the schedule", hold.command);
+        } else if let Action::Move(command) = action {
+            match self.door.command(command) {
+                Ok(_) => queued.push(command),
+                Err(e) => println!("Could not command door: {e}"),
+            }
+        }
+        queued
+    }
+}
+
+/// Runs the scheduler every few seconds against the latest `settings`, moving the door started by [`door::init`]
+/// with the light sensor installed alongside it
+pub async fn run(settings: Arc<Mutex<Settings>>) {
+    let door = match door::handle() {
+        Ok(door) => door,
+        Err(e) => {
+            println!("Could not start the scheduler: {e}");
+            return;
+        },
+    };
+    let clock = door.clock();
+    let mut scheduler = Scheduler::new(door, Box::new(InstalledLightSensor), OneShots::default());
+    loop {
+        match settings.lock() {
+            Ok(settings) => {
+                scheduler.tick(&settings);
+            },
+            Err(_) => println!("Could not aquire settings lock, skipping scheduled operations"),
+        }
+        println!("Sleeping {POLL_STATE_SECS} seconds");
+        clock.sleep(Duration::from_secs(POLL_STATE_SECS)).await;
+    }
+}
+
+/// Whether the light has settled past each light level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LightConditions {
+    /// At or below the close level
+    pub dark: bool,
+    /// At or above the open level
+    pub bright: bool,
+}
+
+/// The command the schedule calls for once the times of day `reached` says have come, if any. Closing takes
+/// precedence over opening.
+pub fn command_for(
+    settings: &Settings,
+    plan: &DayPlan,
+    reached: impl Fn(NaiveTime) -> bool,
+    light: LightConditions,
+) -> Option<Command> {
+    let times = match plan {
+        DayPlan::KeepClosed => return Some(Command::Close),
+        DayPlan::KeepOpen => return Some(Command::Open),
+        DayPlan::Times(times) => times,
+    };
+    let triggers = &settings.triggers;
+    if triggers.close.triggered(times.close, &reached, light.dark) {
+        Some(Command::Close)
+    } else if triggers.open.triggered(times.open, &reached, light.bright) {
+        Some(Command::Open)
+    } else {
+        None
+    }
+}
+
+/// Turns a stream of raw light readings into [`LightConditions`], smoothing them with a rolling median,
+/// holding each light level until the light recovers past its hysteresis band, and only reporting a level
+/// once the light has stayed past it for the dwell time
+#[derive(Debug, Default)]
+pub struct LightTracker {
+    samples: VecDeque<f64>,
+    /// When the light went past the close level, while it stays there
+    dark_since: Option<Instant>,
+    /// When the light went past the open level, while it stays there
+    bright_since: Option<Instant>,
+}
+
+impl LightTracker {
+    /// Feeds in the reading taken at `now` on the monotonic clock, so correcting the wall clock cannot cut a dwell
+    /// short. Without a reading nothing counts as reached and tracking starts over.
+    pub fn update(
+        &mut self,
+        filter: &LightFilter,
+        levels: &LightLevels,
+        now: Instant,
+        reading: Option<f64>,
+    ) -> LightConditions {
+        let Some(reading) = reading else {
+            *self = Self::default();
+            return LightConditions::default();
+        };
+        self.samples.push_back(reading);
+        while self.samples.len() > filter.median_samples.max(1) {
+            self.samples.pop_front();
+        }
+        let level = self.median();
+        let dark = if self.dark_since.is_some() {
+            level <= levels.close + filter.hysteresis
+        } else {
+            level <= levels.close
+        };
+        let bright = if self.bright_since.is_some() {
+            level >= levels.open - filter.hysteresis
+        } else {
+            level >= levels.open
+        };
+        let dwell = Duration::from_secs(filter.dwell_mins * 60);
+        LightConditions {
+            dark: Self::dwelt(&mut self.dark_since, dark, now, dwell),
+            bright: Self::dwelt(&mut self.bright_since, bright, now, dwell),
+        }
+    }
+
+    fn median(&self) -> f64 {
+        let mut sorted: Vec<f64> = self.samples.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let middle = sorted.len() / 2;
+        if sorted.len().is_multiple_of(2) {
+            (sorted[middle - 1] + sorted[middle]) / 2.0
+        } else {
+            sorted[middle]
+        }
+    }
+
+    /// Whether `reached` has held for at least `dwell`, keeping track of when it started in `since`
+    fn dwelt(since: &mut Option<Instant>, reached: bool, now: Instant, dwell: Duration) -> bool {
+        if !reached {
+            *since = None;
+            return false;
+        }
+        now - *since.get_or_insert(now) >= dwell
+    }
+}
+
+/// What the scheduler does with the door on `date`. Lockdown keeps it closed above all else, then the first
+/// date override covering `date` wins, then the weekly times for its day of the week, then the regular fixed
+/// or solar times.
+pub fn day_plan(settings: &Settings, date: NaiveDate) -> DayPlan {
+    if settings.lockdown {
+        return DayPlan::KeepClosed;
+    }
+    if let Some(date_override) = settings.overrides.iter().find(|date_override| date_override.covers(date)) {
+        return date_override.plan.clone();
+    }
+    if let Some(weekday) = settings.weekly.iter().find(|weekday| weekday.weekday == date.weekday()) {
+        return DayPlan::Times(weekday.times.clone());
+    }
+    DayPlan::Times(regular_times(settings, date))
+}
+
+/// Fixed or solar times on `date`. A solar schedule falls back to the fixed times on days without a
+/// sunrise or sunset, or whose offsets carry opening or closing past midnight.
+pub fn regular_times(settings: &Settings, date: NaiveDate) -> Times {
+    match settings.schedule {
+        ScheduleMode::Fixed => settings.times.clone(),
+        ScheduleMode::Solar => sun::solar_times(&settings.solar, date, ScheduleZone::of(settings)).unwrap_or_else(|| {
+            println!("No solar times fit in {date}, using fixed times");
+            settings.times.clone()
+        }),
+    }
+}
@@ -0,0 +1,417 @@
+use crate::settings::LightUnit;
+use rppal::i2c::I2c;
+use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
+use serde::Deserialize;
+use thiserror::Error;
+
+const ADC_CHANNELS: u8 = 8;
+
+/// Anything that can report the current light level in the units of `LightLevels`
+pub trait LightSensor {
+    fn light_level(&mut self) -> Result<f64, LightLevelError>;
+    fn unit(&self) -> LightUnit;
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum LightSensorConfig {
+    Mcp3208(AdcConfig),
+    Mcp3008(AdcConfig),
+    Bh1750(I2cConfig),
+    Tsl2561(I2cConfig),
+    Veml7700(I2cConfig),
+}
+
+impl Default for LightSensorConfig {
+    fn default() -> Self {
+        Self::Mcp3208(AdcConfig::default())
+    }
+}
+
+impl LightSensorConfig {
+    pub fn build(&self) -> Result<Box<dyn LightSensor + Send>, LightLevelError> {
+        Ok(match self {
+            Self::Mcp3208(config) => Box::new(Mcp3x08::new(AdcChip::Mcp3208, config)?),
+            Self::Mcp3008(config) => Box::new(Mcp3x08::new(AdcChip::Mcp3008, config)?),
+            Self::Bh1750(config) => Box::new(Bh1750::new(config)?),
+            Self::Tsl2561(config) => Box::new(Tsl2561::new(config)?),
+            Self::Veml7700(config) => Box::new(Veml7700::new(config)?),
+        })
+    }
+
+    /// GPIO pins taken by the sensor's bus, where they are fixed regardless of device tree overlays
+    pub fn pins(&self) -> Vec<u8> {
+        match self {
+            Self::Mcp3208(config) | Self::Mcp3008(config) => spi_pins(config.bus, config.chip_select),
+            Self::Bh1750(config) | Self::Tsl2561(config) | Self::Veml7700(config) => match config.bus {
+                0 => vec![0, 1],
+                1 => vec![2, 3],
+                _ => Vec::new(),
+            },
+        }
+    }
+
+    /// The ADC channel the sensor reads, if it is on an ADC
+    pub fn adc_channel(&self) -> Option<(u8, u8, u8)> {
+        match self {
+            Self::Mcp3208(config) | Self::Mcp3008(config) => Some((config.bus, config.chip_select, config.channel)),
+            _ => None,
+        }
+    }
+}
+
+fn spi_pins(bus: u8, chip_select: u8) -> Vec<u8> {
+    match (bus, chip_select) {
+        (0, 0) => vec![8, 9, 10, 11],
+        (0, 1) => vec![7, 9, 10, 11],
+        _ => Vec::new(),
+    }
+}
+
+/// Photoresistor read through one channel of an SPI ADC
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AdcConfig {
+    pub bus: u8,
+    pub chip_select: u8,
+    pub channel: u8,
+    pub clock_hz: u32,
+    /// Light level reported when the ADC reads its full reference voltage
+    pub scale: f64,
+    /// Report `scale` at 0V instead, for a photoresistor on the high side of the divider
+    pub invert: bool,
+}
+
+impl Default for AdcConfig {
+    fn default() -> Self {
+        Self {
+            bus: 0,
+            chip_select: 0,
+            channel: 0,
+            clock_hz: 1_000_000,
+            scale: 100.0,
+            invert: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AdcChip {
+    #[default]
+    Mcp3208,
+    Mcp3008,
+}
+
+impl AdcChip {
+    fn resolution(self) -> u32 {
+        match self {
+            Self::Mcp3208 => 4096,
+            Self::Mcp3008 => 1024,
+        }
+    }
+
+    /// Start bit, single ended mode and channel select, aligned so the result ends in the last two bytes
+    fn command(self, channel: u8) -> [u8; 3] {
+        match self {
+            Self::Mcp3208 => [0b110 | (channel >> 2), (channel & 0b11) << 6, 0],
+            Self::Mcp3008 => [1, 0b1000_0000 | (channel << 4), 0],
+        }
+    }
+
+    fn decode(self, response: [u8; 3]) -> u32 {
+        let high_bits = match self {
+            Self::Mcp3208 => response[1] & 0x0F,
+            Self::Mcp3008 => response[1] & 0x03,
+        };
+        (u32::from(high_bits) << 8) | u32::from(response[2])
+    }
+}
+
+/// MCP3208 (12 bit) or MCP3008 (10 bit) ADC on the SPI bus
+pub struct Mcp3x08 {
+    spi: Spi,
+    chip: AdcChip,
+    channel: u8,
+    scale: f64,
+    invert: bool,
+}
+
+impl Mcp3x08 {
+    fn new(chip: AdcChip, config: &AdcConfig) -> Result<Self, LightLevelError> {
+        if config.channel >= ADC_CHANNELS {
+            return Err(LightLevelError::Channel(config.channel));
+        }
+        let bus = match config.bus {
+            0 => Bus::Spi0,
+            1 => Bus::Spi1,
+            2 => Bus::Spi2,
+            3 => Bus::Spi3,
+            4 => Bus::Spi4,
+            5 => Bus::Spi5,
+            6 => Bus::Spi6,
+            bus => return Err(LightLevelError::Bus(bus)),
+        };
+        let chip_select = match config.chip_select {
+            0 => SlaveSelect::Ss0,
+            1 => SlaveSelect::Ss1,
+            2 => SlaveSelect::Ss2,
+            3 => SlaveSelect::Ss3,
+            chip_select => return Err(LightLevelError::ChipSelect(chip_select)),
+        };
+        Ok(Self {
+            spi: Spi::new(bus, chip_select, config.clock_hz, Mode::Mode0)?,
+            chip,
+            channel: config.channel,
+            scale: config.scale,
+            invert: config.invert,
+        })
+    }
+}
+
+impl Mcp3x08 {
+    /// Reading of the channel, scaled to `scale` at the full reference voltage
+    fn read(&mut self) -> Result<f64, LightLevelError> {
+        let write_buffer = self.chip.command(self.channel);
+        let mut read_buffer = [0u8; 3];
+        self.spi.transfer(&mut read_buffer, &write_buffer)?;
+
+        let fraction = f64::from(self.chip.decode(read_buffer)) / f64::from(self.chip.resolution());
+        let fraction = if self.invert { 1.0 - fraction } else { fraction };
+        Ok(fraction * self.scale)
+    }
+}
+
+impl LightSensor for Mcp3x08 {
+    fn light_level(&mut self) -> Result<f64, LightLevelError> {
+        self.read()
+    }
+
+    fn unit(&self) -> LightUnit {
+        LightUnit::Percent
+    }
+}
+
+/// Motor current measured on a spare ADC channel, through a shunt amplifier or hall effect sensor
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CurrentSensorConfig {
+    pub chip: AdcChip,
+    pub bus: u8,
+    pub chip_select: u8,
+    pub channel: u8,
+    pub clock_hz: u32,
+    /// Motor current in amps when the ADC reads its full reference voltage
+    pub scale: f64,
+    /// Current in amps above which the door is treated as obstructed
+    pub threshold: f64,
+}
+
+impl Default for CurrentSensorConfig {
+    fn default() -> Self {
+        Self {
+            chip: AdcChip::default(),
+            bus: 0,
+            chip_select: 0,
+            channel: 1,
+            clock_hz: 1_000_000,
+            scale: 5.0,
+            threshold: 2.0,
+        }
+    }
+}
+
+impl CurrentSensorConfig {
+    pub fn build(&self) -> Result<CurrentSensor, LightLevelError> {
+        let adc = AdcConfig {
+            bus: self.bus,
+            chip_select: self.chip_select,
+            channel: self.channel,
+            clock_hz: self.clock_hz,
+            scale: self.scale,
+            invert: false,
+        };
+        Ok(CurrentSensor {
+            adc: Mcp3x08::new(self.chip, &adc)?,
+            threshold: self.threshold,
+        })
+    }
+
+    pub fn pins(&self) -> Vec<u8> {
+        spi_pins(self.bus, self.chip_select)
+    }
+}
+
+pub struct CurrentSensor {
+    adc: Mcp3x08,
+    threshold: f64,
+}
+
+impl CurrentSensor {
+    pub fn current(&mut self) -> Result<f64, LightLevelError> {
+        self.adc.read()
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+}
+
+/// Lux sensor on the I2C bus
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct I2cConfig {
+    pub bus: u8,
+    /// Defaults to the sensor's factory address
+    pub address: Option<u16>,
+}
+
+impl Default for I2cConfig {
+    fn default() -> Self {
+        Self {
+            bus: 1,
+            address: None,
+        }
+    }
+}
+
+impl I2cConfig {
+    fn open(&self, default_address: u16) -> Result<I2c, LightLevelError> {
+        let mut i2c = I2c::with_bus(self.bus)?;
+        i2c.set_slave_address(self.address.unwrap_or(default_address))?;
+        Ok(i2c)
+    }
+}
+
+/// BH1750 ambient light sensor, run in continuous high resolution mode
+pub struct Bh1750 {
+    i2c: I2c,
+}
+
+impl Bh1750 {
+    const ADDRESS: u16 = 0x23;
+    const POWER_ON: u8 = 0x01;
+    const CONTINUOUS_HIGH_RES: u8 = 0x10;
+    const COUNTS_PER_LUX: f64 = 1.2;
+
+    fn new(config: &I2cConfig) -> Result<Self, LightLevelError> {
+        let mut i2c = config.open(Self::ADDRESS)?;
+        i2c.write(&[Self::POWER_ON])?;
+        i2c.write(&[Self::CONTINUOUS_HIGH_RES])?;
+        Ok(Self { i2c })
+    }
+}
+
+impl LightSensor for Bh1750 {
+    fn light_level(&mut self) -> Result<f64, LightLevelError> {
+        let mut read_buffer = [0u8; 2];
+        self.i2c.read(&mut read_buffer)?;
+        Ok(f64::from(u16::from_be_bytes(read_buffer)) / Self::COUNTS_PER_LUX)
+    }
+
+    fn unit(&self) -> LightUnit {
+        LightUnit::Lux
+    }
+}
+
+/// TSL2561 light-to-digital converter, 402ms integration at 1x gain
+pub struct Tsl2561 {
+    i2c: I2c,
+}
+
+impl Tsl2561 {
+    const ADDRESS: u16 = 0x39;
+    const COMMAND: u8 = 0x80;
+    const WORD: u8 = 0x20;
+    const CONTROL: u8 = 0x00;
+    const TIMING: u8 = 0x01;
+    const DATA0: u8 = 0x0C;
+    const DATA1: u8 = 0x0E;
+    const POWER_ON: u8 = 0x03;
+    const INTEGRATE_402MS_GAIN_1X: u8 = 0x02;
+    /// The datasheet's lux equations assume 16x gain
+    const GAIN_SCALE: f64 = 16.0;
+
+    fn new(config: &I2cConfig) -> Result<Self, LightLevelError> {
+        let i2c = config.open(Self::ADDRESS)?;
+        i2c.smbus_write_byte(Self::COMMAND | Self::CONTROL, Self::POWER_ON)?;
+        i2c.smbus_write_byte(Self::COMMAND | Self::TIMING, Self::INTEGRATE_402MS_GAIN_1X)?;
+        Ok(Self { i2c })
+    }
+}
+
+impl LightSensor for Tsl2561 {
+    fn light_level(&mut self) -> Result<f64, LightLevelError> {
+        let broadband = self.i2c.smbus_read_word(Self::COMMAND | Self::WORD | Self::DATA0)?;
+        let infrared = self.i2c.smbus_read_word(Self::COMMAND | Self::WORD | Self::DATA1)?;
+        if broadband == 0 {
+            return Ok(0.0);
+        }
+        let ch0 = f64::from(broadband) * Self::GAIN_SCALE;
+        let ch1 = f64::from(infrared) * Self::GAIN_SCALE;
+        // Empirical fit for the T, FN and CL packages from the datasheet
+        let ratio = ch1 / ch0;
+        let lux = if ratio <= 0.50 {
+            0.0304 * ch0 - 0.062 * ch0 * ratio.powf(1.4)
+        } else if ratio <= 0.61 {
+            0.0224 * ch0 - 0.031 * ch1
+        } else if ratio <= 0.80 {
+            0.0128 * ch0 - 0.0153 * ch1
+        } else if ratio <= 1.30 {
+            0.00146 * ch0 - 0.00112 * ch1
+        } else {
+            0.0
+        };
+        Ok(lux.max(0.0))
+    }
+
+    fn unit(&self) -> LightUnit {
+        LightUnit::Lux
+    }
+}
+
+/// VEML7700 ambient light sensor, 100ms integration at 1x gain
+pub struct Veml7700 {
+    i2c: I2c,
+}
+
+impl Veml7700 {
+    const ADDRESS: u16 = 0x10;
+    const ALS_CONF: u8 = 0x00;
+    const ALS: u8 = 0x04;
+    /// Gain 1x, 100ms integration, powered on
+    const CONFIG: u16 = 0x0000;
+    const LUX_PER_COUNT: f64 = 0.0576;
+
+    fn new(config: &I2cConfig) -> Result<Self, LightLevelError> {
+        let i2c = config.open(Self::ADDRESS)?;
+        i2c.smbus_write_word(Self::ALS_CONF, Self::CONFIG)?;
+        Ok(Self { i2c })
+    }
+}
+
+impl LightSensor for Veml7700 {
+    fn light_level(&mut self) -> Result<f64, LightLevelError> {
+        Ok(f64::from(self.i2c.smbus_read_word(Self::ALS)?) * Self::LUX_PER_COUNT)
+    }
+
+    fn unit(&self) -> LightUnit {
+        LightUnit::Lux
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum LightLevelError {
+    #[error("could not access ADC")]
+    SPI(#[from] rppal::spi::Error),
+    #[error("could not access I2C light sensor")]
+    I2C(#[from] rppal::i2c::Error),
+    #[error("SPI bus {0} does not exist")]
+    Bus(u8),
+    #[error("SPI chip select {0} does not exist")]
+    ChipSelect(u8),
+    #[error("ADC channel {0} does not exist")]
+    Channel(u8),
+    #[error("no light sensor configured")]
+    NotConfigured,
+}
@@ -1,16 +1,198 @@
+use chrono::{NaiveDate, NaiveTime, Weekday};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
+/// Schema version of settings.toml written by this server. Bump it, and add a step to the migration chain in
+/// `crate::migration`, whenever older files would otherwise be read wrongly.
+pub const SETTINGS_VERSION: u32 = 1;
+
+/// Missing fields take their defaults, so older files still load
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
 pub struct Settings {
+    /// Schema version, 0 for files written before settings were versioned
+    #[serde(default)]
+    pub version: u32,
     pub light_levels: LightLevels,
     pub times: Times,
+    /// IANA timezone the schedule is kept in, e.g. `Europe/London`. The device's timezone if unset.
+    pub timezone: Option<String>,
+    pub schedule: ScheduleMode,
+    pub solar: SolarTimes,
+    /// Times that replace the regular schedule on particular days of the week
+    pub weekly: Vec<WeekdayTimes>,
+    /// Date ranges that replace both the regular and the weekly schedule
+    pub overrides: Vec<DateOverride>,
+    pub triggers: Triggers,
+    pub light_filter: LightFilter,
+    /// Keep the door shut whatever the schedule, light or manual holds say, e.g. while a fox is about
+    pub lockdown: bool,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
+            version: SETTINGS_VERSION,
             light_levels: LightLevels::default(),
             times: Times::default(),
+            timezone: None,
+            schedule: ScheduleMode::default(),
+            solar: SolarTimes::default(),
+            weekly: Vec::new(),
+            overrides: Vec::new(),
+            triggers: Triggers::default(),
+            light_filter: LightFilter::default(),
+            lockdown: false,
+        }
+    }
+}
+
+/// Longest a solar offset can move the door from sunrise or sunset, in minutes
+const MAX_SOLAR_OFFSET_MINS: i64 = 12 * 60;
+
+impl Settings {
+    /// Every problem that would keep the schedule from working as intended. Fixed times and solar settings are
+    /// only checked while they are in use.
+    pub fn validate(&self) -> Result<(), Vec<SettingsError>> {
+        let mut errors = Vec::new();
+        #[cfg(feature = "ssr")]
+        if let Some(name) = &self.timezone {
+            if crate::timezone::ScheduleZone::named(name).is_err() {
+                errors.push(SettingsError::Timezone(name.clone()));
+            }
+        }
+        match self.schedule {
+            ScheduleMode::Fixed if self.times.open >= self.times.close => errors.push(SettingsError::Times),
+            ScheduleMode::Fixed => {},
+            ScheduleMode::Solar => {
+                if !(-90.0..=90.0).contains(&self.solar.latitude) {
+                    errors.push(SettingsError::Latitude);
+                }
+                if !(-180.0..=180.0).contains(&self.solar.longitude) {
+                    errors.push(SettingsError::Longitude);
+                }
+                let offsets = [self.solar.open_offset_mins, self.solar.close_offset_mins];
+                if offsets.iter().any(|offset| offset.abs() > MAX_SOLAR_OFFSET_MINS) {
+                    errors.push(SettingsError::SolarOffset);
+                }
+                #[cfg(feature = "ssr")]
+                if !crate::sun::offsets_fit_in_day(&self.solar, crate::timezone::ScheduleZone::of(self)) {
+                    errors.push(SettingsError::SolarDay);
+                }
+            },
+        }
+        if let TriggerPolicy::LightInWindow { earliest, latest } = self.triggers.open {
+            if earliest >= latest {
+                errors.push(SettingsError::OpenWindow);
+            }
+        }
+        if let TriggerPolicy::LightInWindow { earliest, latest } = self.triggers.close {
+            if earliest >= latest {
+                errors.push(SettingsError::CloseWindow);
+            }
+        }
+        for (index, weekday) in self.weekly.iter().enumerate() {
+            if self.weekly[..index].iter().any(|earlier| earlier.weekday == weekday.weekday) {
+                errors.push(SettingsError::DuplicateWeekday(weekday.weekday));
+            } else if weekday.times.open >= weekday.times.close {
+                errors.push(SettingsError::WeekdayTimes(weekday.weekday));
+            }
+        }
+        for (index, date_override) in self.overrides.iter().enumerate() {
+            if date_override.start > date_override.end {
+                errors.push(SettingsError::OverrideDates(index));
+            }
+            if let DayPlan::Times(times) = &date_override.plan {
+                if times.open >= times.close {
+                    errors.push(SettingsError::OverrideTimes(index));
+                }
+            }
+        }
+        if self.light_levels.open <= self.light_levels.close {
+            errors.push(SettingsError::LightLevels);
+        }
+        if self.light_filter.median_samples == 0 {
+            errors.push(SettingsError::MedianSamples);
+        }
+        let gap = self.light_levels.open - self.light_levels.close;
+        if self.light_filter.hysteresis < 0.0 {
+            errors.push(SettingsError::Hysteresis);
+        } else if gap > 0.0 && self.light_filter.hysteresis >= gap {
+            // Dark would then still count as reached at the open level, and bright at the close level
+            errors.push(SettingsError::HysteresisOverlap);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Part of the settings form a [`SettingsError`] belongs to
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SettingsField {
+    Timezone,
+    Times,
+    Solar,
+    OpenTrigger,
+    CloseTrigger,
+    Weekday(Weekday),
+    /// Index into [`Settings::overrides`]
+    Override(usize),
+    LightLevels,
+    LightFilter,
+}
+
+/// A problem found by [`Settings::validate`]
+#[derive(Error, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum SettingsError {
+    #[error("unknown timezone {0}, expected a name such as Europe/London")]
+    Timezone(String),
+    #[error("open time must be before the close time")]
+    Times,
+    #[error("latitude must be between -90 and 90")]
+    Latitude,
+    #[error("longitude must be between -180 and 180")]
+    Longitude,
+    #[error("offsets must be within 12 hours of sunrise and sunset")]
+    SolarOffset,
+    #[error("offsets must not move opening or closing past midnight")]
+    SolarDay,
+    #[error("opening window must start before it ends")]
+    OpenWindow,
+    #[error("closing window must start before it ends")]
+    CloseWindow,
+    #[error("{0} has its own times more than once")]
+    DuplicateWeekday(Weekday),
+    #[error("{0} must open before it closes")]
+    WeekdayTimes(Weekday),
+    #[error("override must not end before it starts")]
+    OverrideDates(usize),
+    #[error("override must open before it closes")]
+    OverrideTimes(usize),
+    #[error("open light level must be above the close light level")]
+    LightLevels,
+    #[error("light must be smoothed over at least one reading")]
+    MedianSamples,
+    #[error("hysteresis must not be negative")]
+    Hysteresis,
+    #[error("hysteresis must be less than the gap between the open and close light levels")]
+    HysteresisOverlap,
+}
+
+impl SettingsError {
+    pub fn field(&self) -> SettingsField {
+        match self {
+            Self::Timezone(_) => SettingsField::Timezone,
+            Self::Times => SettingsField::Times,
+            Self::Latitude | Self::Longitude | Self::SolarOffset | Self::SolarDay => SettingsField::Solar,
+            Self::OpenWindow => SettingsField::OpenTrigger,
+            Self::CloseWindow => SettingsField::CloseTrigger,
+            Self::DuplicateWeekday(weekday) | Self::WeekdayTimes(weekday) => SettingsField::Weekday(*weekday),
+            Self::OverrideDates(index) | Self::OverrideTimes(index) => SettingsField::Override(*index),
+            Self::LightLevels => SettingsField::LightLevels,
+            Self::MedianSamples | Self::Hysteresis | Self::HysteresisOverlap => SettingsField::LightFilter,
         }
     }
 }
@@ -45,3 +227,208 @@ impl Default for Times {
     }
 }
 
+
+/// Unit of the active light sensor, which `LightLevels` thresholds are expressed in
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LightUnit {
+    /// Uncalibrated reading from the photoresistor
+    #[default]
+    Percent,
+    Lux,
+}
+
+impl LightUnit {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Percent => "%",
+            Self::Lux => "lx",
+        }
+    }
+
+    /// Upper end of the range offered when picking a threshold
+    pub fn max(self) -> f64 {
+        match self {
+            Self::Percent => 100.0,
+            Self::Lux => 1000.0,
+        }
+    }
+}
+
+/// Where the scheduler takes its open and close times from
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleMode {
+    /// The same `Times` every day
+    #[default]
+    Fixed,
+    /// Follow sunrise and sunset as described by `SolarTimes`
+    Solar,
+}
+
+/// Open and close times that follow the sun at the coop's location
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SolarTimes {
+    /// Degrees, north is positive
+    pub latitude: f64,
+    /// Degrees, east is positive
+    pub longitude: f64,
+    pub twilight: Twilight,
+    /// Minutes after sunrise to open, negative to open before it
+    pub open_offset_mins: i64,
+    /// Minutes after sunset to close, negative to close before it
+    pub close_offset_mins: i64,
+}
+
+impl Default for SolarTimes {
+    fn default() -> Self {
+        Self {
+            latitude: 51.48,
+            longitude: 0.0,
+            twilight: Twilight::default(),
+            open_offset_mins: 0,
+            close_offset_mins: 30,
+        }
+    }
+}
+
+/// How far below the horizon the sun is at "sunrise" and "sunset"
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Twilight {
+    /// The upper edge of the sun touches the horizon
+    #[default]
+    Official,
+    /// Sun 6° below the horizon, still light enough to see outdoors
+    Civil,
+    /// Sun 12° below the horizon
+    Nautical,
+}
+
+impl Twilight {
+    pub const ALL: [Self; 3] = [Self::Official, Self::Civil, Self::Nautical];
+
+    /// Angle between the sun and the zenith, in degrees
+    pub fn zenith(self) -> f64 {
+        match self {
+            Self::Official => 90.833,
+            Self::Civil => 96.0,
+            Self::Nautical => 102.0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Official => "official",
+            Self::Civil => "civil",
+            Self::Nautical => "nautical",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|twilight| twilight.name() == name)
+    }
+}
+
+/// What the scheduler does with the door over a day
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DayPlan {
+    /// Open and close at these times, or on the light levels
+    Times(Times),
+    /// Keep the door shut all day, ignoring times and light
+    KeepClosed,
+    /// Keep the door open all day, ignoring times and light
+    KeepOpen,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WeekdayTimes {
+    pub weekday: Weekday,
+    pub times: Times,
+}
+
+/// A run of days that ignore the regular schedule, e.g. a bird flu confinement order
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DateOverride {
+    pub start: NaiveDate,
+    /// Last day of the override, inclusive
+    pub end: NaiveDate,
+    pub plan: DayPlan,
+    #[serde(default)]
+    pub note: String,
+}
+
+impl DateOverride {
+    pub fn covers(&self, date: NaiveDate) -> bool {
+        (self.start..=self.end).contains(&date)
+    }
+}
+
+/// How the open and close times combine with the light levels to move the door
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Triggers {
+    pub open: TriggerPolicy,
+    pub close: TriggerPolicy,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerPolicy {
+    TimeOnly,
+    LightOnly,
+    /// Whichever comes first
+    #[default]
+    TimeOrLight,
+    /// Only once both are reached
+    TimeAndLight,
+    /// On the light level, but only from `earliest`, and at `latest` whatever the light
+    LightInWindow { earliest: NaiveTime, latest: NaiveTime },
+}
+
+impl TriggerPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::TimeOnly => "time_only",
+            Self::LightOnly => "light_only",
+            Self::TimeOrLight => "time_or_light",
+            Self::TimeAndLight => "time_and_light",
+            Self::LightInWindow { .. } => "light_in_window",
+        }
+    }
+
+    /// Whether the door should move at `time`, given which times of day `reached` says have come and whether
+    /// the light level has been reached
+    pub fn triggered(&self, time: NaiveTime, reached: impl Fn(NaiveTime) -> bool, light_reached: bool) -> bool {
+        match self {
+            Self::TimeOnly => reached(time),
+            Self::LightOnly => light_reached,
+            Self::TimeOrLight => reached(time) || light_reached,
+            Self::TimeAndLight => reached(time) && light_reached,
+            Self::LightInWindow { earliest, latest } => reached(*latest) || (reached(*earliest) && light_reached),
+        }
+    }
+}
+
+/// How light readings are cleaned up before the scheduler acts on them
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct LightFilter {
+    /// Readings the rolling median is taken over, 1 to act on every reading as is
+    pub median_samples: usize,
+    /// How far the light must recover past a light level before it counts as no longer reached
+    pub hysteresis: f64,
+    /// Minutes the light must stay past a light level before the door acts on it
+    pub dwell_mins: u64,
+}
+
+impl Default for LightFilter {
+    fn default() -> Self {
+        Self {
+            median_samples: 5,
+            hysteresis: 5.0,
+            dwell_mins: 5,
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::actuator::{ActuatorError, Direction, DoorActuator};
+use crate::clock::Clock;
+use crate::sensor::{LightLevelError, LightSensor};
+use crate::settings::LightUnit;
+use chrono::{Local, NaiveTime, Timelike};
+use std::f64::consts::PI;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Current the simulated motor draws while stalled against an obstruction
+const STALL_CURRENT: f64 = 3.0;
+
+/// Parameters for running the daemon without door hardware
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationConfig {
+    /// Time the motor takes to move the door from fully closed to fully open
+    pub travel_secs: f64,
+    /// Opening time from fully closed after which the limit switch trips.
+    /// Set it above `travel_secs` to simulate a missing or broken switch.
+    pub limit_delay_secs: f64,
+    /// Fit a limit switch at the closed position, tripping once the door is fully closed
+    pub closed_switch: bool,
+    /// Position the door jams at while closing, as if a hen were standing in the doorway
+    pub obstruction: Option<f64>,
+    pub sunrise: NaiveTime,
+    pub sunset: NaiveTime,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            travel_secs: 4.0,
+            limit_delay_secs: 4.0,
+            closed_switch: false,
+            obstruction: None,
+            sunrise: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            sunset: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+        }
+    }
+}
+
+impl SimulationConfig {
+    /// Reads `--simulate` and its tuning flags from the command line.
+    /// Returns `None` when the daemon should use the real hardware.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Option<Self> {
+        let mut args = args.into_iter();
+        let mut simulate = false;
+        let mut config = Self::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--simulate" => simulate = true,
+                "--sim-travel-secs" => parse_next(&mut args, &arg, &mut config.travel_secs),
+                "--sim-limit-delay-secs" => parse_next(&mut args, &arg, &mut config.limit_delay_secs),
+                "--sim-closed-switch" => config.closed_switch = true,
+                "--sim-obstruction" => {
+                    let mut position = 0.5;
+                    parse_next(&mut args, &arg, &mut position);
+                    config.obstruction = Some(position);
+                },
+                "--sim-sunrise" => parse_next(&mut args, &arg, &mut config.sunrise),
+                "--sim-sunset" => parse_next(&mut args, &arg, &mut config.sunset),
+                _ => {},
+            }
+        }
+        simulate.then_some(config)
+    }
+}
+
+fn parse_next<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>, flag: &str, value: &mut T) {
+    match args.next().map(|arg| arg.parse()) {
+        Some(Ok(parsed)) => *value = parsed,
+        _ => println!("Ignoring {flag}, expected a value"),
+    }
+}
+
+/// Door motor and limit switch modelled in software
+pub struct SimulatedActuator {
+    config: SimulationConfig,
+    clock: Arc<dyn Clock>,
+    direction: Direction,
+    enabled: bool,
+    /// 0.0 is fully closed, 1.0 is fully open
+    position: f64,
+    last_update: Instant,
+}
+
+impl SimulatedActuator {
+    pub fn new(config: SimulationConfig, clock: Arc<dyn Clock>) -> Self {
+        let last_update = clock.instant();
+        Self {
+            config,
+            clock,
+            direction: Direction::Open,
+            enabled: false,
+            position: 0.0,
+            last_update,
+        }
+    }
+
+    fn update(&mut self) {
+        let now = self.clock.instant();
+        let elapsed = now - self.last_update;
+        self.last_update = now;
+        if !self.enabled {
+            return;
+        }
+        let travelled = elapsed.as_secs_f64() / self.config.travel_secs;
+        match self.direction {
+            Direction::Open => self.position = (self.position + travelled).min(1.0),
+            Direction::Close => {
+                let stop = match self.config.obstruction {
+                    Some(obstruction) if self.position >= obstruction => obstruction,
+                    _ => 0.0,
+                };
+                self.position = (self.position - travelled).max(stop);
+            },
+        }
+    }
+
+    fn stalled(&self) -> bool {
+        self.enabled
+            && self.direction == Direction::Close
+            && self.config.obstruction.is_some_and(|obstruction| self.position == obstruction)
+    }
+}
+
+impl DoorActuator for SimulatedActuator {
+    fn set_direction(&mut self, direction: Direction) {
+        self.update();
+        self.direction = direction;
+    }
+
+    fn set_motor_enabled(&mut self, enabled: bool) {
+        self.update();
+        self.enabled = enabled;
+    }
+
+    fn open_switch_hit(&mut self) -> Result<bool, ActuatorError> {
+        self.update();
+        Ok(self.position * self.config.travel_secs >= self.config.limit_delay_secs)
+    }
+
+    fn has_closed_switch(&self) -> bool {
+        self.config.closed_switch
+    }
+
+    fn closed_switch_hit(&mut self) -> Result<bool, ActuatorError> {
+        self.update();
+        Ok(self.config.closed_switch && self.position <= 0.0)
+    }
+
+    fn motor_overloaded(&mut self) -> Result<Option<f64>, ActuatorError> {
+        self.update();
+        Ok(self.stalled().then_some(STALL_CURRENT))
+    }
+}
+
+/// Synthetic light curve: dark at night, rising to 100 at solar noon between `sunrise` and `sunset`
+pub struct SimulatedLightSensor {
+    config: SimulationConfig,
+    clock: Arc<dyn Clock>,
+}
+
+impl SimulatedLightSensor {
+    pub fn new(config: SimulationConfig, clock: Arc<dyn Clock>) -> Self {
+        Self { config, clock }
+    }
+}
+
+impl LightSensor for SimulatedLightSensor {
+    fn light_level(&mut self) -> Result<f64, LightLevelError> {
+        let hours = |time: NaiveTime| f64::from(time.num_seconds_from_midnight()) / 3600.0;
+        let now = hours(self.clock.now().with_timezone(&Local).time());
+        let sunrise = hours(self.config.sunrise);
+        let sunset = hours(self.config.sunset);
+        if now <= sunrise || now >= sunset {
+            return Ok(0.0);
+        }
+        Ok(((now - sunrise) / (sunset - sunrise) * PI).sin() * 100.0)
+    }
+
+    fn unit(&self) -> LightUnit {
+        LightUnit::Percent
+    }
+}
@@ -0,0 +1,160 @@
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+
+/// Identifies a command queued on the door
+pub type CommandId = u64;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Command {
+    Open,
+    Close,
+    /// Clear a stop or fault
+    Reset,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum CommandProgress {
+    Queued,
+    Running,
+    Completed,
+    Failed(String),
+    /// Never issued, or too old to still be tracked
+    Unknown,
+}
+
+impl CommandProgress {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Completed | Self::Failed(_) | Self::Unknown)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum State {
+    Open,
+    Opening,
+    Closed,
+    Closing,
+    /// The door hardware misbehaved and needs attention before it is moved again
+    Fault,
+    /// Halted by a person, possibly mid-travel, and held until they reset it
+    Stopped,
+    /// Position has not been established
+    Unknown,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Open => "Open",
+            Self::Opening => "Opening",
+            Self::Closed => "Closed",
+            Self::Closing => "Closing",
+            Self::Fault => "Fault",
+            Self::Stopped => "Stopped",
+            Self::Unknown => "Unknown",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DoorStatus {
+    pub state: State,
+    /// When the door entered `state`
+    pub since: DateTime<Utc>,
+    /// What went wrong, when `state` is [`State::Fault`]
+    pub fault: Option<String>,
+}
+
+impl DoorStatus {
+    pub fn new(state: State, since: DateTime<Utc>) -> Self {
+        Self {
+            state,
+            since,
+            fault: None,
+        }
+    }
+
+    pub fn fault(reason: String, since: DateTime<Utc>) -> Self {
+        Self {
+            fault: Some(reason),
+            ..Self::new(State::Fault, since)
+        }
+    }
+}
+
+/// A motion cut short because the motor drew more current than the obstruction threshold
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ObstructionEvent {
+    pub at: DateTime<Utc>,
+    /// Whether the door was opening or closing
+    pub during: State,
+    /// Motor current in amps when the obstruction was detected
+    pub current: f64,
+}
+
+/// How long a manual command keeps the scheduler from moving the door
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum HoldDuration {
+    /// Until the schedule next changes between opening and closing
+    UntilNextTransition,
+    Hours(u32),
+    /// Until the hold is cancelled
+    Indefinitely,
+}
+
+/// A manual command the scheduler is leaving alone
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ManualHold {
+    pub command: Command,
+    pub since: DateTime<Utc>,
+    pub duration: HoldDuration,
+}
+
+impl ManualHold {
+    pub fn new(command: Command, duration: HoldDuration, since: DateTime<Utc>) -> Self {
+        Self {
+            command,
+            since,
+            duration,
+        }
+    }
+
+    /// When a hold for a number of hours runs out
+    pub fn expires(&self) -> Option<DateTime<Utc>> {
+        match self.duration {
+            HoldDuration::Hours(hours) => Some(self.since + TimeDelta::hours(i64::from(hours))),
+            _ => None,
+        }
+    }
+
+    pub fn expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires().is_some_and(|expires| now >= expires)
+    }
+}
+
+/// A command queued to run once at a set local time, e.g. "open at 09:30 tomorrow"
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OneShotCommand {
+    pub id: u64,
+    pub at: NaiveDateTime,
+    pub command: Command,
+    #[serde(default)]
+    pub note: String,
+}
+
+/// Why a one-shot command was not queued
+#[derive(Error, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum OneShotError {
+    #[error("{} has already passed", .0.format("%Y-%m-%d %H:%M"))]
+    InPast(NaiveDateTime),
+}
+
+/// A time the schedule will move the door
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ScheduledEvent {
+    pub at: NaiveDateTime,
+    pub command: Command,
+}
@@ -0,0 +1,73 @@
+use crate::settings::{SolarTimes, Times};
+use crate::timezone::ScheduleZone;
+use chrono::{DateTime, Datelike, NaiveDate, TimeDelta, Utc};
+
+/// Julian date of 2000-01-01 12:00 UTC
+const J2000: f64 = 2_451_545.0;
+/// Julian date of the Unix epoch
+const UNIX_EPOCH_JD: f64 = 2_440_587.5;
+/// Tilt of the Earth's axis, in degrees
+const OBLIQUITY: f64 = 23.4397;
+/// A leap year, standing in for every year when checking solar settings
+const REFERENCE_YEAR: i32 = 2024;
+
+/// Sunrise and sunset on `date` at `latitude`, `longitude` (degrees, north and east positive), when the sun
+/// is `zenith` degrees from straight overhead. `None` when the sun never crosses that angle, in polar day or night.
+///
+/// Follows the NOAA sunrise equation, which is accurate to about a minute away from the poles.
+pub fn sunrise_sunset(date: NaiveDate, latitude: f64, longitude: f64, zenith: f64) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1)?;
+    let days = (date - epoch).num_days() as f64;
+    // Mean solar noon
+    let noon = days - longitude / 360.0;
+    let anomaly = (357.5291 + 0.985_600_28 * noon).rem_euclid(360.0).to_radians();
+    let center = 1.9148 * anomaly.sin() + 0.0200 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic_longitude = (anomaly.to_degrees() + center + 180.0 + 102.9372).rem_euclid(360.0).to_radians();
+    let transit = J2000 + noon + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+    let declination = (ecliptic_longitude.sin() * OBLIQUITY.to_radians().sin()).asin();
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = (zenith.to_radians().cos() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees() / 360.0;
+    Some((from_julian(transit - hour_angle)?, from_julian(transit + hour_angle)?))
+}
+
+fn from_julian(julian: f64) -> Option<DateTime<Utc>> {
+    let millis = ((julian - UNIX_EPOCH_JD) * 86_400_000.0).round() as i64;
+    DateTime::from_timestamp_millis(millis)
+}
+
+/// Open and close times in `zone` on `date` for a solar schedule. `None` if the sun does not rise or set that
+/// day, or if opening or closing falls on another day, which would leave the door closing before it opens.
+pub fn solar_times(solar: &SolarTimes, date: NaiveDate, zone: ScheduleZone) -> Option<Times> {
+    let (sunrise, sunset) = sunrise_sunset(date, solar.latitude, solar.longitude, solar.twilight.zenith())?;
+    let open = zone.local(sunrise + TimeDelta::minutes(solar.open_offset_mins));
+    let close = zone.local(sunset + TimeDelta::minutes(solar.close_offset_mins));
+    if open.date() != date || close.date() != date || open >= close {
+        return None;
+    }
+    Some(Times {
+        open: open.time(),
+        close: close.time(),
+    })
+}
+
+/// Whether the offsets in `solar` keep opening before closing on the same day, on every day of the year that
+/// sunrise and sunset manage to without them
+pub fn offsets_fit_in_day(solar: &SolarTimes, zone: ScheduleZone) -> bool {
+    let unshifted = SolarTimes {
+        open_offset_mins: 0,
+        close_offset_mins: 0,
+        ..solar.clone()
+    };
+    let Some(new_year) = NaiveDate::from_ymd_opt(REFERENCE_YEAR, 1, 1) else {
+        return true;
+    };
+    new_year
+        .iter_days()
+        .take_while(|date| date.year() == REFERENCE_YEAR)
+        .all(|date| solar_times(&unshifted, date, zone).is_none() || solar_times(solar, date, zone).is_some())
+}
@@ -0,0 +1,54 @@
+use crate::clock::Clock;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+static TIME_SYNC: OnceLock<TimeSyncConfig> = OnceLock::new();
+
+/// When the clock is trusted for time based actions, from the `[hardware.time_sync]` section of hardware.toml
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct TimeSyncConfig {
+    /// Any earlier date means the clock was never set, e.g. 1970 after booting without a real time clock
+    pub minimum_date: NaiveDate,
+    /// Also wait for the kernel to report the clock synchronized, as chrony or another NTP client does
+    pub require_sync: bool,
+}
+
+impl Default for TimeSyncConfig {
+    fn default() -> Self {
+        Self {
+            minimum_date: NaiveDate::from_ymd_opt(2025, 1, 1).unwrap(),
+            require_sync: true,
+        }
+    }
+}
+
+pub fn init(config: TimeSyncConfig) {
+    if TIME_SYNC.set(config).is_err() {
+        println!("Time sync already initialized");
+    }
+}
+
+/// Whether `clock` can be trusted to say what time of day it is
+pub fn check(clock: &dyn Clock) -> Result<(), UntrustedClock> {
+    let default = TimeSyncConfig::default();
+    let config = TIME_SYNC.get().unwrap_or(&default);
+    let today = clock.now().date_naive();
+    if today < config.minimum_date {
+        return Err(UntrustedClock::NotSet(today));
+    }
+    if config.require_sync && !clock.synchronized() {
+        return Err(UntrustedClock::NotSynchronized);
+    }
+    Ok(())
+}
+
+#[derive(Error, Debug)]
+pub enum UntrustedClock {
+    #[error("clock has not been set, it reads {0}")]
+    NotSet(NaiveDate),
+    #[error("clock is not synchronized")]
+    NotSynchronized,
+}
@@ -0,0 +1,75 @@
+use crate::settings::Settings;
+use chrono::{DateTime, LocalResult, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use thiserror::Error;
+
+/// Longest run of local time a timezone change has ever skipped, when Samoa crossed the date line in 2011
+const LONGEST_GAP_MINS: i64 = 24 * 60;
+
+/// Timezone the schedule is kept in. Times in settings and one-shot commands are wall clock times in this zone.
+///
+/// Clocks changing for daylight saving time skip or repeat an hour of wall clock time. A time that is skipped
+/// takes effect as soon as the clocks go forward, and a time that is repeated takes effect the first time
+/// round only.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScheduleZone {
+    /// Whatever the device is set to
+    #[default]
+    System,
+    Iana(Tz),
+}
+
+impl ScheduleZone {
+    /// Parses an IANA timezone name such as `Europe/London`
+    pub fn named(name: &str) -> Result<Self, TimezoneError> {
+        name.parse().map(Self::Iana).map_err(|_| TimezoneError::Unknown(name.to_string()))
+    }
+
+    /// The zone `settings` ask for, or the system's if none is set or its name is unknown
+    pub fn of(settings: &Settings) -> Self {
+        settings.timezone.as_deref().and_then(|name| Self::named(name).ok()).unwrap_or_default()
+    }
+
+    /// Wall clock time in this zone at `at`
+    pub fn local(&self, at: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Self::System => at.with_timezone(&chrono::Local).naive_local(),
+            Self::Iana(tz) => at.with_timezone(tz).naive_local(),
+        }
+    }
+
+    pub fn today(&self, now: DateTime<Utc>) -> NaiveDate {
+        self.local(now).date()
+    }
+
+    /// The moment the wall clock in this zone reaches `local`
+    pub fn instant(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        match self {
+            Self::System => resolve(&chrono::Local, local),
+            Self::Iana(tz) => resolve(tz, local),
+        }
+    }
+
+    /// The wall clock time at which something scheduled for `local` happens, which differs from `local`
+    /// only when the clocks skip over it
+    pub fn effective(&self, local: NaiveDateTime) -> NaiveDateTime {
+        self.local(self.instant(local))
+    }
+}
+
+fn resolve<Z: TimeZone>(zone: &Z, local: NaiveDateTime) -> DateTime<Utc> {
+    match zone.from_local_datetime(&local) {
+        LocalResult::Single(at) | LocalResult::Ambiguous(at, _) => at.with_timezone(&Utc),
+        // Skipped by the clocks going forward, so look for where the wall clock carries on
+        LocalResult::None => (1..=LONGEST_GAP_MINS)
+            .find_map(|minutes| zone.from_local_datetime(&(local + TimeDelta::minutes(minutes))).earliest())
+            .map(|at| at.with_timezone(&Utc))
+            .unwrap_or_else(|| local.and_utc()),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TimezoneError {
+    #[error("unknown timezone {0}, expected an IANA name such as Europe/London")]
+    Unknown(String),
+}
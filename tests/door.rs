@@ -0,0 +1,198 @@
+//! Runs the door state machine against the simulated hardware on a fake clock
+
+use chicken_door::actuator::{ActuatorError, Direction, DoorActuator};
+use chicken_door::clock::{Clock, FakeClock};
+use chicken_door::door::{self, Door, DoorError, OneShotIOError, OneShots};
+use chicken_door::hardware::MotionTimings;
+use chicken_door::settings::Settings;
+use chicken_door::simulation::{SimulatedActuator, SimulationConfig};
+use chicken_door::status::{Command, HoldDuration, OneShotError, State};
+use chicken_door::time_sync;
+use chicken_door::timezone::ScheduleZone;
+use chrono::{NaiveDate, NaiveDateTime, TimeZone, Utc};
+use std::sync::{Arc, Once};
+use std::time::Duration;
+
+/// The simulated door with an open limit switch that bounces shut once, early in the travel
+struct BouncingSwitch {
+    door: SimulatedActuator,
+    polls: u32,
+    bounce_at: u32,
+}
+
+impl DoorActuator for BouncingSwitch {
+    fn set_direction(&mut self, direction: Direction) {
+        self.door.set_direction(direction);
+    }
+
+    fn set_motor_enabled(&mut self, enabled: bool) {
+        self.door.set_motor_enabled(enabled);
+    }
+
+    fn open_switch_hit(&mut self) -> Result<bool, ActuatorError> {
+        self.polls += 1;
+        Ok(self.polls == self.bounce_at || self.door.open_switch_hit()?)
+    }
+}
+
+/// Moves the tests into a scratch directory of their own, where the door keeps its files
+fn in_scratch_dir() {
+    static SCRATCH: Once = Once::new();
+    SCRATCH.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("chicken-door-tests-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_current_dir(&dir).unwrap();
+    });
+}
+
+fn clock() -> Arc<FakeClock> {
+    Arc::new(FakeClock::new(Utc.with_ymd_and_hms(2025, 6, 9, 6, 0, 0).unwrap()))
+}
+
+#[tokio::test]
+async fn bouncing_limit_switch_does_not_end_the_open_early() {
+    let clock = clock();
+    let config = SimulationConfig::default();
+    let actuator = BouncingSwitch {
+        door: SimulatedActuator::new(config.clone(), clock.clone()),
+        polls: 0,
+        bounce_at: 20,
+    };
+    let mut door = Door::new(actuator, MotionTimings::default(), clock.clone(), State::Closed);
+    let start = clock.instant();
+    door.open().await.unwrap();
+
+    assert_eq!(door.state(), State::Open);
+    assert!(clock.instant() - start >= Duration::from_secs_f64(config.travel_secs));
+}
+
+#[tokio::test]
+async fn homes_to_the_open_switch_after_an_interrupted_open() {
+    let clock = clock();
+    let config = SimulationConfig::default();
+    let actuator = SimulatedActuator::new(config.clone(), clock.clone());
+    let mut door = Door::new(actuator, MotionTimings::default(), clock.clone(), State::Unknown);
+    let start = clock.instant();
+    door.home(Some(State::Opening)).await.unwrap();
+
+    assert_eq!(door.state(), State::Open);
+    assert!(clock.instant() - start >= Duration::from_secs_f64(config.travel_secs));
+}
+
+#[tokio::test]
+async fn trusts_a_door_that_had_settled_closed() {
+    let clock = clock();
+    let actuator = SimulatedActuator::new(SimulationConfig::default(), clock.clone());
+    let mut door = Door::new(actuator, MotionTimings::default(), clock.clone(), State::Unknown);
+    let start = clock.instant();
+    door.home(Some(State::Closed)).await.unwrap();
+
+    assert_eq!(door.state(), State::Closed);
+    assert!(clock.instant() - start < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn closing_from_an_unknown_position_opens_to_the_switch_first() {
+    let clock = clock();
+    let config = SimulationConfig::default();
+    let timings = MotionTimings::default();
+    let actuator = SimulatedActuator::new(config.clone(), clock.clone());
+    let mut door = Door::new(actuator, timings, clock.clone(), State::Unknown);
+    let start = clock.instant();
+    door.close().await.unwrap();
+
+    assert_eq!(door.state(), State::Closed);
+    let homed_and_closed = config.travel_secs + timings.door_close_secs as f64;
+    assert!(clock.instant() - start >= Duration::from_secs_f64(homed_and_closed));
+}
+
+#[tokio::test]
+async fn stop_keeps_the_fault_that_needs_attention() {
+    let clock = clock();
+    let config = SimulationConfig {
+        obstruction: Some(0.5),
+        ..SimulationConfig::default()
+    };
+    let actuator = SimulatedActuator::new(config, clock.clone());
+    let mut door = Door::new(actuator, MotionTimings::default(), clock, State::Closed);
+    door.open().await.unwrap();
+    assert!(door.close().await.is_err());
+    let fault = door.subscribe().borrow().clone();
+    assert_eq!(fault.state, State::Fault);
+    assert!(fault.fault.is_some());
+
+    door.halt();
+    assert_eq!(*door.subscribe().borrow(), fault);
+
+    door.reset();
+    assert_eq!(door.state(), State::Unknown);
+}
+
+#[test]
+fn opening_by_hand_is_refused_during_lockdown() {
+    in_scratch_dir();
+    door::write_settings(Settings {
+        lockdown: true,
+        ..Settings::default()
+    })
+    .unwrap();
+
+    let result = door::manual_command(Command::Open, HoldDuration::Indefinitely);
+    assert!(matches!(result, Err(DoorError::LockedDown)), "{result:?}");
+}
+
+fn london() -> ScheduleZone {
+    ScheduleZone::named("Europe/London").unwrap()
+}
+
+fn june(day: u32, hour: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2025, 6, day).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+}
+
+#[test]
+fn one_shot_ids_are_never_reused() {
+    in_scratch_dir();
+    let now = london().instant(june(9, 8));
+    let add = |at| door::add_one_shot(now, london(), at, Command::Close, String::new()).unwrap();
+    let first = add(june(9, 12));
+    let second = add(june(10, 12));
+    door::cancel_one_shot(second).unwrap();
+    let due = OneShots::default().take_due(london().instant(june(9, 12)), london()).unwrap();
+    assert_eq!(due.iter().map(|one_shot| one_shot.id).collect::<Vec<_>>(), vec![first]);
+    assert!(door::one_shots().unwrap().is_empty());
+
+    let third = add(june(11, 12));
+    assert!(third > second, "id {third} reused");
+}
+
+#[test]
+fn one_shots_in_the_past_are_refused() {
+    let now = london().instant(june(9, 8));
+    let result = door::add_one_shot(now, london(), june(9, 7), Command::Open, String::new());
+    assert!(
+        matches!(result, Err(OneShotIOError::Rejected(OneShotError::InPast(at))) if at == june(9, 7)),
+        "{result:?}"
+    );
+}
+
+#[tokio::test]
+async fn holds_and_clock_checks_go_by_the_door_clock() {
+    in_scratch_dir();
+    // Homes straight to closed, without the door task having to move it
+    std::fs::write("door_state.toml", "state = \"Closed\"\n").unwrap();
+    let clock = clock();
+    let actuator = SimulatedActuator::new(SimulationConfig::default(), clock.clone());
+    door::init(actuator, MotionTimings::default(), clock.clone());
+
+    door::manual_command(Command::Close, HoldDuration::Hours(2)).unwrap();
+    assert_eq!(door::manual_hold(clock.now()).map(|hold| hold.since), Some(clock.now()));
+    clock.advance(Duration::from_secs(2 * 3600 - 1));
+    assert!(door::manual_hold(clock.now()).is_some());
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(door::manual_hold(clock.now()), None);
+
+    // The control panel warns about the installed clock, not the system one
+    assert!(time_sync::check(door::clock().as_ref()).is_ok());
+    clock.set_synchronized(false);
+    assert!(time_sync::check(door::clock().as_ref()).is_err());
+}
@@ -0,0 +1,586 @@
+//! Runs the scheduler against the simulated door over several days of simulated time, and checks
+//! the trigger policies they are made from
+
+use chicken_door::clock::{Clock, FakeClock};
+use chicken_door::door::{Door, DoorHandle, OneShots};
+use chicken_door::hardware::MotionTimings;
+use chicken_door::scheduler::{command_for, preview, LightConditions, LightTracker, Scheduler};
+use chicken_door::settings::{
+    DateOverride, DayPlan, LightFilter, LightLevels, Settings, Times, TriggerPolicy, Triggers,
+};
+use chicken_door::simulation::{SimulatedActuator, SimulatedLightSensor, SimulationConfig};
+use chicken_door::status::{Command, HoldDuration, ScheduledEvent, State};
+use chicken_door::timezone::ScheduleZone;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Utc};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often the simulated scheduler ticks
+const TICK_SECS: i64 = 60;
+
+/// The scheduler moving the simulated door by the simulated light, all on one fake clock
+struct Coop {
+    clock: Arc<FakeClock>,
+    door: Arc<DoorHandle>,
+    scheduler: Scheduler,
+    one_shots: OneShots,
+    /// Where `one_shots` are kept, removed along with the coop
+    one_shot_file: PathBuf,
+    settings: Settings,
+    /// Every command the scheduler queued for the door, in the schedule's timezone
+    moves: Vec<(NaiveDateTime, Command)>,
+}
+
+impl Coop {
+    fn new(settings: Settings, config: SimulationConfig, start: DateTime<Utc>) -> Self {
+        static COOPS: AtomicU32 = AtomicU32::new(0);
+        let clock = Arc::new(FakeClock::new(start));
+        let actuator = SimulatedActuator::new(config.clone(), clock.clone());
+        let door = Door::new(actuator, MotionTimings::default(), clock.clone(), State::Unknown);
+        let door = Arc::new(DoorHandle::spawn(door, Some(State::Closed)));
+        let sensor = SimulatedLightSensor::new(config, clock.clone());
+        let coop = COOPS.fetch_add(1, Ordering::Relaxed);
+        let file = format!("chicken-door-one-shots-{}-{coop}.toml", std::process::id());
+        let one_shot_file = std::env::temp_dir().join(file);
+        let one_shots = OneShots::new(&one_shot_file);
+        Self {
+            scheduler: Scheduler::new(door.clone(), Box::new(sensor), one_shots.clone()),
+            clock,
+            door,
+            one_shots,
+            one_shot_file,
+            settings,
+            moves: Vec::new(),
+        }
+    }
+
+    async fn run_for(&mut self, duration: TimeDelta) {
+        self.door.idle().await;
+        let end = self.clock.now() + duration;
+        let mut tick = self.clock.now();
+        while tick < end {
+            self.clock.advance_to(tick);
+            self.tick().await;
+            tick += TimeDelta::seconds(TICK_SECS);
+        }
+        self.clock.advance_to(end);
+    }
+
+    /// Ticks the scheduler and lets the door finish whatever it was told to do
+    async fn tick(&mut self) {
+        let local = ScheduleZone::of(&self.settings).local(self.clock.now());
+        for command in self.scheduler.tick(&self.settings) {
+            self.moves.push((local, command));
+        }
+        self.door.idle().await;
+    }
+
+    fn state(&self) -> State {
+        self.door.status().state
+    }
+}
+
+impl Drop for Coop {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.one_shot_file);
+    }
+}
+
+fn day(offset: u64) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2025, 6, 9).unwrap() + chrono::Days::new(offset)
+}
+
+fn at(date: NaiveDate, hour: u32, minute: u32) -> NaiveDateTime {
+    date.and_time(NaiveTime::from_hms_opt(hour, minute, 0).unwrap())
+}
+
+fn local_midnight(date: NaiveDate) -> DateTime<Utc> {
+    Local.from_local_datetime(&at(date, 0, 0)).single().unwrap().with_timezone(&Utc)
+}
+
+/// Wall clock `local` in London, where the clocks went forward on 2025-03-30 and back on 2025-10-26
+fn london(local: NaiveDateTime) -> DateTime<Utc> {
+    ScheduleZone::named("Europe/London").unwrap().instant(local)
+}
+
+fn time_only() -> Settings {
+    Settings {
+        triggers: Triggers {
+            open: TriggerPolicy::TimeOnly,
+            close: TriggerPolicy::TimeOnly,
+        },
+        ..Settings::default()
+    }
+}
+
+/// Time based triggers, with light levels the simulated daylight passes around 08:00 and 17:50
+fn time_only_with_light_levels() -> Settings {
+    Settings {
+        light_levels: LightLevels { open: 50.0, close: 5.0 },
+        ..time_only()
+    }
+}
+
+/// The simulated light passes 50% at 08:00 and drops below 5% shortly before sunset at 18:00, and the median
+/// and dwell time delay each by a few minutes
+fn assert_followed_the_light(moves: &[(NaiveDateTime, Command)], date: NaiveDate) {
+    let [(opened, Command::Open), (closed, Command::Close)] = moves else {
+        panic!("expected an open then a close on {date}, got {moves:?}");
+    };
+    assert!((at(date, 8, 0)..at(date, 8, 30)).contains(opened), "opened at {opened}");
+    assert!((at(date, 17, 45)..at(date, 18, 15)).contains(closed), "closed at {closed}");
+}
+
+#[tokio::test]
+async fn fixed_times_repeat_every_day() {
+    let mut coop = Coop::new(time_only(), SimulationConfig::default(), local_midnight(day(0)));
+    coop.run_for(TimeDelta::days(3)).await;
+
+    let expected: Vec<_> = (0..3)
+        .flat_map(|offset| [(at(day(offset), 6, 0), Command::Open), (at(day(offset), 18, 0), Command::Close)])
+        .collect();
+    assert_eq!(coop.moves, expected);
+    assert_eq!(coop.state(), State::Closed);
+}
+
+#[tokio::test]
+async fn override_takes_effect_at_midnight() {
+    let settings = Settings {
+        overrides: vec![DateOverride {
+            start: day(1),
+            end: day(1),
+            plan: DayPlan::KeepClosed,
+            note: "Vet visit".to_string(),
+        }],
+        ..time_only()
+    };
+    let mut coop = Coop::new(settings, SimulationConfig::default(), local_midnight(day(0)));
+    coop.run_for(TimeDelta::days(3)).await;
+
+    assert_eq!(
+        coop.moves,
+        vec![
+            (at(day(0), 6, 0), Command::Open),
+            (at(day(0), 18, 0), Command::Close),
+            (at(day(2), 6, 0), Command::Open),
+            (at(day(2), 18, 0), Command::Close),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn light_triggers_follow_the_simulated_daylight() {
+    let settings = Settings {
+        light_levels: LightLevels { open: 50.0, close: 5.0 },
+        triggers: Triggers {
+            open: TriggerPolicy::LightOnly,
+            close: TriggerPolicy::LightOnly,
+        },
+        ..Settings::default()
+    };
+    let mut coop = Coop::new(settings, SimulationConfig::default(), local_midnight(day(0)));
+    coop.run_for(TimeDelta::days(3)).await;
+
+    assert_eq!(coop.moves.len(), 6, "{:?}", coop.moves);
+    for (offset, moves) in coop.moves.chunks(2).enumerate() {
+        assert_followed_the_light(moves, day(offset as u64));
+    }
+}
+
+#[tokio::test]
+async fn obstruction_blocks_the_schedule_until_reset() {
+    let config = SimulationConfig {
+        obstruction: Some(0.5),
+        ..SimulationConfig::default()
+    };
+    let mut coop = Coop::new(time_only(), config, local_midnight(day(0)));
+    let zone = ScheduleZone::of(&coop.settings);
+    let note = "Let the birds out".to_string();
+    coop.one_shots.add(coop.clock.now(), zone, at(day(1), 12, 0), Command::Open, note).unwrap();
+    coop.run_for(TimeDelta::days(3)).await;
+
+    // Neither the schedule nor the one-shot touch the faulted door
+    assert_eq!(
+        coop.moves,
+        vec![(at(day(0), 6, 0), Command::Open), (at(day(0), 18, 0), Command::Close)]
+    );
+    assert_eq!(coop.state(), State::Fault);
+
+    coop.door.command(Command::Reset).unwrap();
+    coop.run_for(TimeDelta::hours(7)).await;
+    assert_eq!(coop.moves.last(), Some(&(at(day(3), 6, 0), Command::Open)));
+    assert_eq!(coop.state(), State::Open);
+    // Far too late to run by the time the door was reset
+    assert!(coop.one_shots.pending().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn one_shot_holds_the_door_until_the_schedule_changes() {
+    let mut coop = Coop::new(time_only(), SimulationConfig::default(), local_midnight(day(0)));
+    let zone = ScheduleZone::of(&coop.settings);
+    let note = "Worming".to_string();
+    coop.one_shots.add(coop.clock.now(), zone, at(day(0), 12, 0), Command::Close, note).unwrap();
+    coop.run_for(TimeDelta::days(2)).await;
+
+    // Left closed from noon rather than reopened on the next tick, until the schedule closes it anyway
+    assert_eq!(
+        coop.moves,
+        vec![
+            (at(day(0), 6, 0), Command::Open),
+            (at(day(0), 12, 0), Command::Close),
+            (at(day(1), 6, 0), Command::Open),
+            (at(day(1), 18, 0), Command::Close),
+        ]
+    );
+    assert!(coop.one_shots.pending().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn lockdown_cancels_a_manual_hold() {
+    let mut coop = Coop::new(time_only(), SimulationConfig::default(), local_midnight(day(0)));
+    coop.run_for(TimeDelta::hours(9)).await;
+    coop.door.manual_command(Command::Close, HoldDuration::Indefinitely).unwrap();
+    coop.run_for(TimeDelta::hours(1)).await;
+    assert_eq!(coop.state(), State::Closed);
+
+    coop.settings.lockdown = true;
+    coop.run_for(TimeDelta::hours(1)).await;
+    coop.settings.lockdown = false;
+    coop.run_for(TimeDelta::hours(1)).await;
+
+    // The hold went with the lockdown, so the schedule reopens the door as soon as it ends
+    let [opened, reopened] = &coop.moves[..] else {
+        panic!("expected the scheduled open and a reopen, got {:?}", coop.moves);
+    };
+    assert_eq!(*opened, (at(day(0), 6, 0), Command::Open));
+    assert_eq!(reopened.1, Command::Open);
+    assert!((at(day(0), 11, 0)..at(day(0), 11, 1)).contains(&reopened.0), "reopened at {}", reopened.0);
+    assert_eq!(coop.state(), State::Open);
+}
+
+/// Opens at 01:30 London time, which is skipped when the clocks go forward
+fn london_small_hours() -> Settings {
+    Settings {
+        timezone: Some("Europe/London".to_string()),
+        times: Times {
+            open: NaiveTime::from_hms_opt(1, 30, 0).unwrap(),
+            close: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+        },
+        ..time_only()
+    }
+}
+
+#[tokio::test]
+async fn skipped_time_takes_effect_when_the_clocks_go_forward() {
+    let spring = NaiveDate::from_ymd_opt(2025, 3, 30).unwrap();
+    let mut coop = Coop::new(london_small_hours(), SimulationConfig::default(), london(at(spring, 0, 0)));
+    coop.run_for(TimeDelta::days(1)).await;
+
+    // 00:59 GMT is followed by 02:00 BST
+    assert_eq!(
+        coop.moves,
+        vec![(at(spring, 2, 0), Command::Open), (at(spring, 18, 0), Command::Close)]
+    );
+}
+
+#[tokio::test]
+async fn repeated_time_takes_effect_once() {
+    let autumn = NaiveDate::from_ymd_opt(2025, 10, 26).unwrap();
+    let mut coop = Coop::new(london_small_hours(), SimulationConfig::default(), london(at(autumn, 0, 0)));
+    coop.run_for(TimeDelta::days(1)).await;
+
+    // 01:30 comes round twice, first in BST then in GMT
+    assert_eq!(
+        coop.moves,
+        vec![(at(autumn, 1, 30), Command::Open), (at(autumn, 18, 0), Command::Close)]
+    );
+}
+
+#[tokio::test]
+async fn repeated_close_time_is_not_undone_when_the_clocks_go_back() {
+    let settings = Settings {
+        times: Times {
+            open: NaiveTime::from_hms_opt(0, 30, 0).unwrap(),
+            close: NaiveTime::from_hms_opt(1, 30, 0).unwrap(),
+        },
+        ..london_small_hours()
+    };
+    let autumn = NaiveDate::from_ymd_opt(2025, 10, 26).unwrap();
+    let mut coop = Coop::new(settings, SimulationConfig::default(), london(at(autumn, 0, 0)));
+    coop.run_for(TimeDelta::days(1)).await;
+
+    // Once closed at 01:30 BST the door stays shut through the repeated 01:00 to 02:00 GMT
+    assert_eq!(
+        coop.moves,
+        vec![(at(autumn, 0, 30), Command::Open), (at(autumn, 1, 30), Command::Close)]
+    );
+}
+
+#[test]
+fn preview_shows_skipped_times_when_the_clocks_go_forward() {
+    let settings = london_small_hours();
+    let spring = NaiveDate::from_ymd_opt(2025, 3, 30).unwrap();
+    let events = preview(&settings, at(spring, 0, 0), at(spring, 23, 59));
+    assert_eq!(
+        events,
+        vec![
+            ScheduledEvent { at: at(spring, 2, 0), command: Command::Open },
+            ScheduledEvent { at: at(spring, 18, 0), command: Command::Close },
+        ]
+    );
+}
+
+#[test]
+fn preview_leaves_out_triggers_that_wait_on_the_light() {
+    let settings = Settings {
+        triggers: Triggers {
+            open: TriggerPolicy::TimeAndLight,
+            close: TriggerPolicy::TimeOnly,
+        },
+        ..Settings::default()
+    };
+    let events = preview(&settings, at(day(0), 0, 0), at(day(0), 23, 59));
+    assert_eq!(events, vec![ScheduledEvent { at: at(day(0), 18, 0), command: Command::Close }]);
+}
+
+#[test]
+fn explicit_timezone_ignores_the_system_timezone() {
+    let zone = ScheduleZone::named("Pacific/Auckland").unwrap();
+    let noon_utc = Utc.with_ymd_and_hms(2025, 6, 9, 12, 0, 0).unwrap();
+    assert_eq!(zone.local(noon_utc), at(day(1), 0, 0));
+    assert_eq!(zone.instant(at(day(1), 0, 0)), noon_utc);
+    assert!(ScheduleZone::named("Middle/Earth").is_err());
+}
+
+#[tokio::test]
+async fn unset_clock_follows_the_light_until_it_is_set() {
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+    let mut coop = Coop::new(time_only_with_light_levels(), SimulationConfig::default(), local_midnight(epoch));
+    coop.run_for(TimeDelta::days(1)).await;
+    assert_followed_the_light(&coop.moves, epoch);
+
+    coop.clock.set(local_midnight(day(0)));
+    coop.run_for(TimeDelta::days(1)).await;
+    assert_eq!(
+        coop.moves[2..],
+        [(at(day(0), 6, 0), Command::Open), (at(day(0), 18, 0), Command::Close)]
+    );
+}
+
+#[tokio::test]
+async fn unsynchronized_clock_follows_the_light_until_it_synchronizes() {
+    let mut coop = Coop::new(time_only_with_light_levels(), SimulationConfig::default(), local_midnight(day(0)));
+    coop.clock.set_synchronized(false);
+    coop.run_for(TimeDelta::days(1)).await;
+    assert_followed_the_light(&coop.moves, day(0));
+
+    coop.clock.set_synchronized(true);
+    coop.run_for(TimeDelta::days(1)).await;
+    assert_eq!(
+        coop.moves[2..],
+        [(at(day(1), 6, 0), Command::Open), (at(day(1), 18, 0), Command::Close)]
+    );
+}
+
+#[tokio::test]
+async fn fake_clock_sleeps_without_waiting() {
+    let start = local_midnight(day(0));
+    let clock = FakeClock::new(start);
+    let instant = clock.instant();
+    clock.sleep(Duration::from_secs(3600)).await;
+    assert_eq!(clock.now(), start + TimeDelta::hours(1));
+    assert_eq!(clock.instant() - instant, Duration::from_secs(3600));
+
+    // Setting the wall clock back leaves monotonic time alone
+    clock.set(start);
+    assert_eq!(clock.now(), start);
+    assert_eq!(clock.instant() - instant, Duration::from_secs(3600));
+}
+
+fn hm(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+}
+
+/// Every time of day up to and including `now` has been reached
+fn until(now: NaiveTime) -> impl Fn(NaiveTime) -> bool {
+    move |time| time <= now
+}
+
+const NO_LIGHT_LEVEL: LightConditions = LightConditions { dark: false, bright: false };
+const DARK: LightConditions = LightConditions { dark: true, bright: false };
+const BRIGHT: LightConditions = LightConditions { dark: false, bright: true };
+
+#[test]
+fn every_trigger_policy_combines_time_and_light_as_documented() {
+    let window = TriggerPolicy::LightInWindow { earliest: hm(7, 0), latest: hm(9, 0) };
+    let cases = [
+        (TriggerPolicy::TimeOnly, hm(7, 59), true, false),
+        (TriggerPolicy::TimeOnly, hm(8, 0), false, true),
+        (TriggerPolicy::LightOnly, hm(23, 0), false, false),
+        (TriggerPolicy::LightOnly, hm(0, 0), true, true),
+        (TriggerPolicy::TimeOrLight, hm(7, 59), false, false),
+        (TriggerPolicy::TimeOrLight, hm(7, 59), true, true),
+        (TriggerPolicy::TimeOrLight, hm(8, 0), false, true),
+        (TriggerPolicy::TimeAndLight, hm(8, 0), false, false),
+        (TriggerPolicy::TimeAndLight, hm(7, 59), true, false),
+        (TriggerPolicy::TimeAndLight, hm(8, 0), true, true),
+        (window.clone(), hm(6, 59), true, false),
+        (window.clone(), hm(7, 0), true, true),
+        (window.clone(), hm(8, 59), false, false),
+        (window, hm(9, 0), false, true),
+    ];
+    for (policy, now, light_reached, expected) in cases {
+        assert_eq!(
+            policy.triggered(hm(8, 0), until(now), light_reached),
+            expected,
+            "{policy:?} at {now}, light reached {light_reached}"
+        );
+    }
+}
+
+#[test]
+fn command_for_follows_each_trigger_policy() {
+    let plan = DayPlan::Times(Times { open: hm(6, 0), close: hm(18, 0) });
+    let windows = Triggers {
+        open: TriggerPolicy::LightInWindow { earliest: hm(5, 0), latest: hm(7, 0) },
+        close: TriggerPolicy::LightInWindow { earliest: hm(17, 0), latest: hm(19, 0) },
+    };
+    let same = |policy: TriggerPolicy| Triggers { open: policy.clone(), close: policy };
+    let cases = [
+        (same(TriggerPolicy::TimeOnly), hm(5, 0), BRIGHT, None),
+        (same(TriggerPolicy::TimeOnly), hm(6, 0), NO_LIGHT_LEVEL, Some(Command::Open)),
+        (same(TriggerPolicy::TimeOnly), hm(12, 0), DARK, Some(Command::Open)),
+        (same(TriggerPolicy::TimeOnly), hm(18, 0), BRIGHT, Some(Command::Close)),
+        (same(TriggerPolicy::LightOnly), hm(5, 0), BRIGHT, Some(Command::Open)),
+        (same(TriggerPolicy::LightOnly), hm(12, 0), NO_LIGHT_LEVEL, None),
+        (same(TriggerPolicy::LightOnly), hm(12, 0), DARK, Some(Command::Close)),
+        (same(TriggerPolicy::LightOnly), hm(19, 0), NO_LIGHT_LEVEL, None),
+        (same(TriggerPolicy::TimeOrLight), hm(5, 0), NO_LIGHT_LEVEL, None),
+        (same(TriggerPolicy::TimeOrLight), hm(5, 0), BRIGHT, Some(Command::Open)),
+        (same(TriggerPolicy::TimeOrLight), hm(12, 0), NO_LIGHT_LEVEL, Some(Command::Open)),
+        (same(TriggerPolicy::TimeOrLight), hm(12, 0), DARK, Some(Command::Close)),
+        (same(TriggerPolicy::TimeAndLight), hm(5, 0), BRIGHT, None),
+        (same(TriggerPolicy::TimeAndLight), hm(12, 0), NO_LIGHT_LEVEL, None),
+        (same(TriggerPolicy::TimeAndLight), hm(12, 0), DARK, None),
+        (same(TriggerPolicy::TimeAndLight), hm(12, 0), BRIGHT, Some(Command::Open)),
+        (same(TriggerPolicy::TimeAndLight), hm(18, 0), DARK, Some(Command::Close)),
+        (windows.clone(), hm(4, 59), BRIGHT, None),
+        (windows.clone(), hm(5, 0), BRIGHT, Some(Command::Open)),
+        (windows.clone(), hm(6, 30), NO_LIGHT_LEVEL, None),
+        // The end of a window moves the door whatever the light
+        (windows.clone(), hm(7, 0), NO_LIGHT_LEVEL, Some(Command::Open)),
+        (windows.clone(), hm(16, 59), DARK, Some(Command::Open)),
+        (windows.clone(), hm(17, 0), DARK, Some(Command::Close)),
+        (windows.clone(), hm(18, 0), NO_LIGHT_LEVEL, Some(Command::Open)),
+        (windows, hm(19, 0), BRIGHT, Some(Command::Close)),
+    ];
+    for (triggers, now, light, expected) in cases {
+        let settings = Settings { triggers: triggers.clone(), ..Settings::default() };
+        assert_eq!(
+            command_for(&settings, &plan, until(now), light),
+            expected,
+            "{triggers:?} at {now} with {light:?}"
+        );
+    }
+}
+
+#[test]
+fn closing_wins_when_both_directions_trigger() {
+    let settings = Settings::default();
+    let plan = DayPlan::Times(Times { open: hm(6, 0), close: hm(18, 0) });
+    // Past the open time but dark, e.g. a storm
+    assert_eq!(command_for(&settings, &plan, until(hm(12, 0)), DARK), Some(Command::Close));
+    // Still bright after the close time, e.g. a summer evening
+    assert_eq!(command_for(&settings, &plan, until(hm(18, 0)), BRIGHT), Some(Command::Close));
+
+    let open_all_day = DayPlan::KeepOpen;
+    let closed_all_day = DayPlan::KeepClosed;
+    assert_eq!(command_for(&settings, &open_all_day, until(hm(23, 0)), DARK), Some(Command::Open));
+    assert_eq!(command_for(&settings, &closed_all_day, until(hm(12, 0)), BRIGHT), Some(Command::Close));
+}
+
+/// Close and open light levels for the light tracker tests, each with the default hysteresis of 5 around it
+const LEVELS: LightLevels = LightLevels { open: 50.0, close: 5.0 };
+
+/// Feeds `readings`, one a minute from `start`, returning the conditions after each
+/// Feeds `readings` to `tracker` a minute apart on `clock`
+fn feed(tracker: &mut LightTracker, filter: &LightFilter, clock: &FakeClock, readings: &[f64]) -> Vec<LightConditions> {
+    readings
+        .iter()
+        .map(|reading| {
+            let conditions = tracker.update(filter, &LEVELS, clock.instant(), Some(*reading));
+            clock.advance(Duration::from_secs(60));
+            conditions
+        })
+        .collect()
+}
+
+#[test]
+fn median_rejects_a_brief_spike() {
+    let filter = LightFilter { dwell_mins: 0, ..LightFilter::default() };
+    let clock = FakeClock::new(local_midnight(day(0)));
+
+    // Headlights sweeping across the sensor at night
+    let mut tracker = LightTracker::default();
+    let conditions = feed(&mut tracker, &filter, &clock, &[0.0, 0.0, 0.0, 0.0, 100.0, 0.0]);
+    assert!(conditions.iter().all(|light| light.dark && !light.bright), "{conditions:?}");
+
+    // A shadow passing over it by day
+    let mut tracker = LightTracker::default();
+    let conditions = feed(&mut tracker, &filter, &clock, &[80.0, 80.0, 80.0, 80.0, 0.0, 80.0]);
+    assert!(conditions.iter().all(|light| light.bright && !light.dark), "{conditions:?}");
+}
+
+#[test]
+fn light_level_stays_reached_until_it_recovers_past_the_hysteresis() {
+    let filter = LightFilter { median_samples: 1, dwell_mins: 0, ..LightFilter::default() };
+    let clock = FakeClock::new(local_midnight(day(0)));
+    let mut tracker = LightTracker::default();
+    let dark: Vec<_> = feed(&mut tracker, &filter, &clock, &[4.0, 8.0, 9.9, 10.1, 8.0])
+        .iter()
+        .map(|light| light.dark)
+        .collect();
+    // 8 is above the close level but within the hysteresis band, so it only counts once the light has gone past 10
+    assert_eq!(dark, [true, true, true, false, false]);
+
+    let mut tracker = LightTracker::default();
+    let bright: Vec<_> = feed(&mut tracker, &filter, &clock, &[50.0, 46.0, 45.1, 44.9, 47.0])
+        .iter()
+        .map(|light| light.bright)
+        .collect();
+    assert_eq!(bright, [true, true, true, false, false]);
+}
+
+#[test]
+fn interrupted_dwell_starts_over() {
+    let filter = LightFilter { median_samples: 1, ..LightFilter::default() };
+    let clock = FakeClock::new(local_midnight(day(0)));
+    let mut tracker = LightTracker::default();
+    // Dark for four minutes, a minute of light past the hysteresis band, then dark again
+    let readings = [2.0, 2.0, 2.0, 2.0, 20.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0];
+    let dark: Vec<_> = feed(&mut tracker, &filter, &clock, &readings)
+        .iter()
+        .map(|light| light.dark)
+        .collect();
+    // The five minute dwell counts from minute 5, not minute 0
+    assert_eq!(dark, [false, false, false, false, false, false, false, false, false, false, true]);
+}
+
+#[test]
+fn setting_the_clock_forward_does_not_cut_the_dwell_short() {
+    let filter = LightFilter { median_samples: 1, ..LightFilter::default() };
+    let clock = FakeClock::new(local_midnight(day(0)));
+    let mut tracker = LightTracker::default();
+    let dark: Vec<_> = feed(&mut tracker, &filter, &clock, &[2.0, 2.0]).iter().map(|light| light.dark).collect();
+    assert_eq!(dark, [false, false]);
+
+    // NTP corrects the wall clock by an hour two minutes into the five minute dwell
+    clock.set(clock.now() + TimeDelta::hours(1));
+    let dark: Vec<_> = feed(&mut tracker, &filter, &clock, &[2.0, 2.0, 2.0, 2.0])
+        .iter()
+        .map(|light| light.dark)
+        .collect();
+    assert_eq!(dark, [false, false, false, true]);
+}
@@ -0,0 +1,161 @@
+//! Checks settings are validated before they reach the scheduler, and older settings files are upgraded
+
+use chicken_door::migration::{self, MigrationError};
+use chicken_door::settings::{
+    DateOverride, DayPlan, LightLevels, ScheduleMode, Settings, SettingsError, SettingsField, Times, WeekdayTimes,
+    SETTINGS_VERSION,
+};
+use chicken_door::sun;
+use chicken_door::timezone::ScheduleZone;
+use chrono::{NaiveDate, NaiveTime, Weekday};
+
+fn times(open: u32, close: u32) -> Times {
+    Times {
+        open: NaiveTime::from_hms_opt(open, 0, 0).unwrap(),
+        close: NaiveTime::from_hms_opt(close, 0, 0).unwrap(),
+    }
+}
+
+#[test]
+fn default_settings_are_valid() {
+    assert_eq!(Settings::default().validate(), Ok(()));
+}
+
+#[test]
+fn every_problem_is_reported_against_its_field() {
+    let settings = Settings {
+        timezone: Some("Europe/Narnia".to_string()),
+        times: times(20, 8),
+        light_levels: LightLevels { open: 10.0, close: 40.0 },
+        weekly: vec![
+            WeekdayTimes { weekday: Weekday::Sat, times: times(9, 7) },
+            WeekdayTimes { weekday: Weekday::Sun, times: times(8, 20) },
+            WeekdayTimes { weekday: Weekday::Sun, times: times(9, 20) },
+        ],
+        overrides: vec![
+            DateOverride {
+                start: NaiveDate::from_ymd_opt(2025, 6, 2).unwrap(),
+                end: NaiveDate::from_ymd_opt(2025, 6, 1).unwrap(),
+                plan: DayPlan::KeepClosed,
+                note: String::new(),
+            },
+        ],
+        ..Settings::default()
+    };
+    let errors = settings.validate().unwrap_err();
+    assert_eq!(
+        errors,
+        vec![
+            SettingsError::Timezone("Europe/Narnia".to_string()),
+            SettingsError::Times,
+            SettingsError::WeekdayTimes(Weekday::Sat),
+            SettingsError::DuplicateWeekday(Weekday::Sun),
+            SettingsError::OverrideDates(0),
+            SettingsError::LightLevels,
+        ]
+    );
+    let fields: Vec<_> = errors.iter().map(SettingsError::field).collect();
+    assert_eq!(
+        fields,
+        vec![
+            SettingsField::Timezone,
+            SettingsField::Times,
+            SettingsField::Weekday(Weekday::Sat),
+            SettingsField::Weekday(Weekday::Sun),
+            SettingsField::Override(0),
+            SettingsField::LightLevels,
+        ]
+    );
+}
+
+#[test]
+fn hysteresis_must_leave_a_gap_between_the_light_levels() {
+    let mut settings = Settings {
+        light_levels: LightLevels { open: 40.0, close: 10.0 },
+        ..Settings::default()
+    };
+    settings.light_filter.hysteresis = 29.9;
+    assert_eq!(settings.validate(), Ok(()));
+
+    settings.light_filter.hysteresis = 30.0;
+    let errors = settings.validate().unwrap_err();
+    assert_eq!(errors, vec![SettingsError::HysteresisOverlap]);
+    assert_eq!(errors[0].field(), SettingsField::LightFilter);
+}
+
+#[test]
+fn fixed_times_are_not_checked_while_following_the_sun() {
+    let mut settings = Settings {
+        schedule: ScheduleMode::Solar,
+        times: times(20, 8),
+        ..Settings::default()
+    };
+    assert_eq!(settings.validate(), Ok(()));
+
+    settings.solar.latitude = 95.0;
+    assert_eq!(settings.validate(), Err(vec![SettingsError::Latitude]));
+}
+
+#[test]
+fn solar_offsets_must_not_carry_the_door_past_midnight() {
+    let mut settings = Settings {
+        schedule: ScheduleMode::Solar,
+        timezone: Some("Europe/London".to_string()),
+        ..Settings::default()
+    };
+    assert_eq!(settings.validate(), Ok(()));
+
+    // Midsummer sunset is after 21:00, so closing six hours later would be in the small hours of the next day
+    settings.solar.close_offset_mins = 6 * 60;
+    assert_eq!(settings.validate(), Err(vec![SettingsError::SolarDay]));
+    let zone = ScheduleZone::of(&settings);
+    let midsummer = NaiveDate::from_ymd_opt(2025, 6, 21).unwrap();
+    let midwinter = NaiveDate::from_ymd_opt(2025, 12, 21).unwrap();
+    assert_eq!(sun::solar_times(&settings.solar, midsummer, zone), None);
+    assert!(sun::solar_times(&settings.solar, midwinter, zone).is_some());
+}
+
+#[test]
+fn unversioned_settings_are_upgraded() {
+    let mut table: toml::Table = toml::from_str(
+        r#"
+        [light_levels]
+        close = 10.0
+        open = 60.0
+
+        [times]
+        open = "07:00:00"
+        close = "21:00:00"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(migration::migrate(&mut table).unwrap(), 0);
+    assert_eq!(migration::version(&table).unwrap(), SETTINGS_VERSION);
+
+    let settings: Settings = table.try_into().unwrap();
+    assert_eq!(
+        settings,
+        Settings {
+            light_levels: LightLevels { open: 60.0, close: 10.0 },
+            times: times(7, 21),
+            ..Settings::default()
+        }
+    );
+}
+
+#[test]
+fn current_settings_are_left_alone() {
+    let original = toml::Table::try_from(Settings::default()).unwrap();
+    let mut table = original.clone();
+    assert_eq!(migration::migrate(&mut table).unwrap(), SETTINGS_VERSION);
+    assert_eq!(table, original);
+}
+
+#[test]
+fn settings_from_a_newer_server_are_refused() {
+    let mut table: toml::Table = toml::from_str(&format!("version = {}", SETTINGS_VERSION + 1)).unwrap();
+    assert!(matches!(migration::migrate(&mut table), Err(MigrationError::Newer(_))));
+
+    let mut table: toml::Table = toml::from_str("version = \"one\"").unwrap();
+    assert!(matches!(migration::migrate(&mut table), Err(MigrationError::InvalidVersion)));
+}